@@ -1,15 +1,38 @@
 use bus::Bus;
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
 
-use crate::tf2::{lobby::Lobby, logfile::LogLine};
+use crate::{
+    models::player_db::PlayerDbCommand,
+    tf2::{
+        lobby::{Lobby, LobbyCommand},
+        logfile::LogLine,
+        playerlists::SubscribedPlayerlist,
+        steam::{SteamReply, SteamRequest},
+    },
+};
+
+/// How long to wait before trying a full bus again, see send_to_lobby()
+const SEND_RETRY_DELAY: Duration = Duration::from_millis(10);
 
 pub struct AppBus {
     pub logfile_bus: Bus<LogLine>,
     pub rcon_bus: Bus<String>,
     pub lobby_report_bus: Bus<Lobby>,
+    pub lobby_cmd_bus: Bus<LobbyCommand>,
+    pub player_db_bus: Bus<PlayerDbCommand>,
+    pub playerlists_bus: Bus<SubscribedPlayerlist>,
+    pub steam_request_bus: Bus<SteamRequest>,
+    pub steam_reply_bus: Bus<SteamReply>,
 
     pub rcon_thread_handle: Option<std::thread::JoinHandle<()>>,
     pub lobby_thread_handle: Option<std::thread::JoinHandle<()>>,
     pub logfile_watcher_thread_handle: Option<std::thread::JoinHandle<()>>,
+    pub playerlists_thread_handle: Option<std::thread::JoinHandle<()>>,
+    pub steam_thread_handle: Option<std::thread::JoinHandle<()>>,
 }
 
 impl Default for AppBus {
@@ -24,19 +47,69 @@ impl AppBus {
             logfile_bus: Bus::new(100000),
             rcon_bus: Bus::new(10),
             lobby_report_bus: Bus::new(10),
+            lobby_cmd_bus: Bus::new(10),
+            player_db_bus: Bus::new(10),
+            playerlists_bus: Bus::new(10),
+            steam_request_bus: Bus::new(1),
+            steam_reply_bus: Bus::new(10),
 
             rcon_thread_handle: None,
             lobby_thread_handle: None,
             logfile_watcher_thread_handle: None,
+            playerlists_thread_handle: None,
+            steam_thread_handle: None,
+        }
+    }
+
+    pub fn send_logline(bus: &Arc<Mutex<AppBus>>, logline: LogLine) {
+        Self::send_to_lobby(bus, |bus| &mut bus.logfile_bus, logline);
+    }
+
+    /// Returns false if the GUI is behind and the report was dropped.
+    /// The lobby thread then sends a newer one later, so the latest lobby always gets through.
+    pub fn send_lobby_report(&mut self, lobby: Lobby) -> bool {
+        self.lobby_report_bus.try_broadcast(lobby).is_ok()
+    }
+
+    /// Returns false if the Steam thread is still busy with the last request.
+    /// The lobby thread asks again later.
+    pub fn send_steam_request(&mut self, request: SteamRequest) -> bool {
+        self.steam_request_bus.try_broadcast(request).is_ok()
+    }
+
+    pub fn send_steam_reply(bus: &Arc<Mutex<AppBus>>, reply: SteamReply) {
+        Self::send_to_lobby(bus, |bus| &mut bus.steam_reply_bus, reply);
+    }
+
+    /// Waits for room on a bus the lobby thread reads, without holding the bus lock.
+    /// The lobby thread takes the lock to send its reports before it reads more,
+    /// so waiting with the lock held would leave both threads waiting on each other.
+    fn send_to_lobby<T: Clone + Sync>(
+        bus: &Arc<Mutex<AppBus>>,
+        pick: fn(&mut AppBus) -> &mut Bus<T>,
+        mut msg: T,
+    ) {
+        loop {
+            match pick(&mut bus.lock().unwrap()).try_broadcast(msg) {
+                Ok(()) => return,
+                Err(returned) => msg = returned,
+            }
+            thread::sleep(SEND_RETRY_DELAY);
         }
     }
 
-    pub fn send_logline(&mut self, logline: LogLine) {
-        self.logfile_bus.broadcast(logline);
+    pub fn send_playerlist(bus: &Arc<Mutex<AppBus>>, playerlist: SubscribedPlayerlist) {
+        Self::send_to_lobby(bus, |bus| &mut bus.playerlists_bus, playerlist);
+    }
+
+    /// Send a command to the lobby thread
+    pub fn send_lobby_cmd(&mut self, cmd: LobbyCommand) {
+        self.lobby_cmd_bus.broadcast(cmd);
     }
 
-    pub fn send_lobby_report(&mut self, lobby: Lobby) {
-        self.lobby_report_bus.broadcast(lobby);
+    /// Send a command to the lobby thread, which owns the player database
+    pub fn send_player_db_cmd(&mut self, cmd: PlayerDbCommand) {
+        self.player_db_bus.broadcast(cmd);
     }
 
     /// Send a RCON command to the TF2 RCON
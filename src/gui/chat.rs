@@ -1,6 +1,6 @@
 use eframe::egui::{text::LayoutJob, Color32, ScrollArea, TextFormat, TextStyle, Ui};
 
-use crate::tf2::lobby::{Lobby, LobbyChat, Player, Team};
+use crate::tf2::lobby::{ChatKind, Lobby, LobbyChat, Player, Team};
 
 use super::colors::hexrgb;
 
@@ -41,6 +41,11 @@ pub fn add_chat(ui: &mut Ui, lobby: &Lobby, swap_team_colors: &mut bool) {
 }
 
 fn add_chat_row(ui: &mut Ui, lobby: &Lobby, chat: &LobbyChat, swap_team_colors: &mut bool) {
+    if chat.kind != ChatKind::Player {
+        add_other_chat_row(ui, chat);
+        return;
+    }
+
     let player = lobby.get_player(None, Some(chat.steamid));
     if player.is_none() {
         return;
@@ -121,6 +126,32 @@ fn add_chat_row(ui: &mut Ui, lobby: &Lobby, chat: &LobbyChat, swap_team_colors:
 
     ui.label(job);
 }
+
+/// Server messages and chat from names that are not players, greyed out
+fn add_other_chat_row(ui: &mut Ui, chat: &LobbyChat) {
+    let text = if chat.name.is_empty() {
+        chat.message.clone()
+    } else {
+        format!("{}: {}", chat.name, chat.message)
+    };
+
+    let mut job = LayoutJob::default();
+    job.append(
+        &text,
+        0.0,
+        TextFormat {
+            color: Color32::DARK_GRAY,
+            italics: true,
+            ..Default::default()
+        },
+    );
+
+    let hover = match chat.kind {
+        ChatKind::Server => "From the server",
+        _ => "Not from a player in the server, could be spoofed",
+    };
+    ui.label(job).on_hover_text(hover);
+}
 // pub fn add_chat(ui: &mut Ui, lobby: &Lobby) {
 //     let text_style = TextStyle::Body;
 //     let row_height = ui.text_style_height(&text_style);
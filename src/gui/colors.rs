@@ -46,6 +46,20 @@ pub fn set_style(ctx: &egui::Context) {
     ctx.set_visuals(style);
 }
 
+/// Colors for the party markers, reused when there are more parties than colors
+const PARTY_COLORS: [Color32; 6] = [
+    ORANGE,
+    LIGHT_BEIGE,
+    hexrgb(0x9b59b6),
+    hexrgb(0x1abc9c),
+    hexrgb(0xe84393),
+    hexrgb(0x7f8c8d),
+];
+
+pub fn color_for_party(party: u32) -> Color32 {
+    PARTY_COLORS[party as usize % PARTY_COLORS.len()]
+}
+
 /// Returns the (text color, background color) for a given flag.
 pub fn color_for_flag(flag: PlayerFlags) -> (Color32, Color32) {
     match flag {
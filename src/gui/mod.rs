@@ -3,6 +3,7 @@ pub mod chat;
 pub mod colors;
 pub mod image_creds;
 pub mod player_tooltip;
+pub mod playerlist_menu;
 pub mod scoreboard;
 pub mod scoreboard_team;
 
@@ -46,6 +47,8 @@ impl eframe::App for AppWin {
 
         self.process_bus();
 
+        add_menu_row(ctx, &self.bus, &mut self.playerlist_filename);
+
         egui::CentralPanel::default().show(ctx, |ui| {
             scoreboard::add_scoreboard(
                 ui,
@@ -54,6 +57,7 @@ impl eframe::App for AppWin {
                 &mut self.lobby,
                 &mut self.swap_team_colors,
                 &mut self.show_crits,
+                &mut self.sort_by_hours,
             );
 
             ui.separator();
@@ -65,11 +69,13 @@ impl eframe::App for AppWin {
     }
 }
 
-// fn add_menu_row(ctx: &egui::Context) {
-//     egui::TopBottomPanel::top("menu").show(ctx, |ui| {
-//         ui.label("Menus...");
-//     });
-// }
+fn add_menu_row(ctx: &egui::Context, bus: &Arc<Mutex<AppBus>>, playerlist_filename: &mut String) {
+    egui::TopBottomPanel::top("menu").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            playerlist_menu::add_playerlist_menu(ui, bus, playerlist_filename);
+        });
+    });
+}
 
 // fn add_status_row(ctx: &egui::Context) {
 //     egui::TopBottomPanel::bottom("status").show(ctx, |ui| {
@@ -1,11 +1,14 @@
 use eframe::egui::{Image, Ui};
 
-use crate::tf2::lobby::{Player, PlayerKill};
+use crate::{
+    models::flag_description,
+    tf2::lobby::{FriendList, Lobby, Player, PlayerKill},
+};
 
 // pub const IMAGE_URL: &str =
 //     "https://avatars.cloudflare.steamstatic.com/f39ba23bc07d2de9b77abcabae13ee2541f9c938_full.jpg";
 
-pub fn add_player_tooltip(ui: &mut Ui, player: &Player) {
+pub fn add_player_tooltip(ui: &mut Ui, lobby: &Lobby, player: &Player) {
     ui.heading(&player.name);
     // ui.heading(format!("({}) {}", player.id, &player.name));
 
@@ -20,9 +23,124 @@ pub fn add_player_tooltip(ui: &mut Ui, player: &Player) {
             steam_info.get_account_created()
         ));
 
+        if let Some(playtime) = &steam_info.tf2_playtime {
+            ui.label(format!("TF2 hours: {}", playtime));
+        }
+
+        if let Some(bans) = &steam_info.bans {
+            if bans.has_bans() {
+                ui.label(format!("VAC bans: {}", bans.vac_bans));
+                ui.label(format!("Game bans: {}", bans.game_bans));
+                if bans.community_banned {
+                    ui.label("Community banned");
+                }
+                if bans.economy_ban != "none" {
+                    ui.label(format!("Economy ban: {}", bans.economy_ban));
+                }
+                ui.label(format!("Days since last ban: {}", bans.days_since_last_ban));
+            } else {
+                ui.label("No bans");
+            }
+        }
+
+        ui.separator();
+    }
+
+    if !player.flags.is_empty() || !player.notes.is_empty() {
+        for entry in &player.flags {
+            let mut text = format!(
+                "{}: added {} by {}",
+                flag_description(entry.flag),
+                entry.added.format("%Y-%m-%d"),
+                entry.added_by
+            );
+            if let Some(source) = &entry.source {
+                text.push_str(&format!(" (from playerlist '{}')", source));
+            }
+            ui.label(text);
+        }
+
+        if !player.notes.is_empty() {
+            ui.label(format!("Notes: {}", player.notes));
+        }
+
+        ui.separator();
+    }
+
+    let friends = lobby.friends_of(player.steamid);
+    if !friends.is_empty() {
+        ui.label("Friends in this server:");
+        for friend in friends {
+            // Being friends with a flagged player is worth knowing
+            let flags: Vec<&str> = friend
+                .flags
+                .iter()
+                .map(|f| flag_description(f.flag))
+                .collect();
+            if flags.is_empty() {
+                ui.label(&friend.name);
+            } else {
+                ui.label(format!("{} ({})", friend.name, flags.join(", ")));
+            }
+        }
+        ui.separator();
+    } else if player
+        .steam_info
+        .as_ref()
+        .is_some_and(|info| info.friends == Some(FriendList::Private))
+    {
+        ui.label("Friend list is private");
+        ui.separator();
+    }
+
+    if !player.rule_matches.is_empty() {
+        for rule_match in &player.rule_matches {
+            ui.label(format!(
+                "{} Rule '{}' matched '{}'",
+                rule_match.when.format("%H:%M:%S"),
+                rule_match.rule,
+                rule_match.text
+            ));
+        }
+
         ui.separator();
     }
 
+    if !player.previous_names.is_empty() {
+        ui.label(format!("Renamed {} times. Previous names:", player.renames));
+        // Latest first, bots can have a long history
+        for previous in player.previous_names.iter().rev().take(10) {
+            ui.label(format!(
+                "{} {}",
+                previous.until.format("%H:%M:%S"),
+                previous.name
+            ));
+        }
+        ui.separator();
+    }
+
+    if !player.found_items.is_empty() || !player.achievements.is_empty() {
+        ui.label("Found items and achievements:");
+        for found in player.found_items.iter().rev().take(10) {
+            ui.label(format!("{} {}", found.when.format("%H:%M:%S"), found.item));
+        }
+        for earned in player.achievements.iter().rev().take(10) {
+            ui.label(format!(
+                "{} Achievement: {}",
+                earned.when.format("%H:%M:%S"),
+                earned.achievement
+            ));
+        }
+        ui.separator();
+    }
+
+    if player.captures > 0 || player.defenses > 0 {
+        ui.label(format!(
+            "Captures: {}, defenses: {}",
+            player.captures, player.defenses
+        ));
+    }
+
     ui.label(format!("Console ID in game: {}", player.id));
     //  .on_hover_text("This ID is used in TF2 console commands to vote kick");
 
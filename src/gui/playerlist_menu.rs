@@ -0,0 +1,40 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{appbus::AppBus, models::player_db::PlayerDbCommand};
+use eframe::egui::{TextEdit, Ui};
+
+/// Menu to import and export TF2 Bot Detector playerlist files
+pub fn add_playerlist_menu(ui: &mut Ui, bus: &Arc<Mutex<AppBus>>, filename: &mut String) {
+    ui.menu_button("☰ Playerlist", |ui| {
+        ui.label("TF2 Bot Detector playerlist file:");
+        ui.add(TextEdit::singleline(filename).desired_width(300.0));
+
+        ui.horizontal(|ui| {
+            if ui
+                .button("Import")
+                .on_hover_text("Add the players in the file to your player list")
+                .clicked()
+            {
+                bus.lock()
+                    .unwrap()
+                    .send_player_db_cmd(PlayerDbCommand::ImportTf2bd {
+                        filename: filename.clone(),
+                    });
+                ui.close_menu();
+            }
+
+            if ui
+                .button("Export")
+                .on_hover_text("Write your flagged players to the file")
+                .clicked()
+            {
+                bus.lock()
+                    .unwrap()
+                    .send_player_db_cmd(PlayerDbCommand::ExportTf2bd {
+                        filename: filename.clone(),
+                    });
+                ui.close_menu();
+            }
+        });
+    });
+}
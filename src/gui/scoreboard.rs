@@ -7,9 +7,9 @@ use super::{
 use crate::{
     appbus::AppBus,
     models::steamid::SteamID,
-    tf2::lobby::{Lobby, Player, Team},
+    tf2::lobby::{Lobby, Player, Team, VoteResult},
 };
-use eframe::egui::{Color32, Ui};
+use eframe::egui::{Color32, Grid, Ui};
 
 pub fn add_scoreboard(
     ui: &mut Ui,
@@ -18,10 +18,14 @@ pub fn add_scoreboard(
     lobby: &mut Lobby,
     swap_team_colors: &mut bool,
     show_crits: &mut bool,
+    sort_by_hours: &mut bool,
 ) {
     // ui.heading("Scoreboard");
     let image_desc = add_background_image(ui);
 
+    add_server_info(ui, lobby);
+    add_vote_info(ui, lobby);
+
     let mut sorted_players: Vec<Player> = lobby.players.clone();
 
     ui.horizontal(|ui| {
@@ -45,7 +49,11 @@ pub fn add_scoreboard(
 
     ui.separator();
 
-    sorted_players.sort_by(cmp_for_scoreboard);
+    if *sort_by_hours {
+        sorted_players.sort_by(cmp_by_hours);
+    } else {
+        sorted_players.sort_by(cmp_for_scoreboard);
+    }
 
     let blu: Vec<&Player> = sorted_players
         .iter()
@@ -63,18 +71,22 @@ pub fn add_scoreboard(
             bus,
             "Blu",
             self_steamid,
+            lobby,
             &blu,
             "blu",
             show_crits,
+            sort_by_hours,
         );
         scoreboard_team(
             &mut ui[1],
             bus,
             "Red",
             self_steamid,
+            lobby,
             &red,
             "red",
             show_crits,
+            sort_by_hours,
         );
     });
 
@@ -112,6 +124,97 @@ pub fn add_scoreboard(
             .join(", ");
         ui.colored_label(Color32::GRAY, format!("Joined: {}", player_names));
     }
+
+    add_recently_left(ui, lobby);
+}
+
+/// Players that left, newest first, with the stats they had when leaving
+fn add_recently_left(ui: &mut Ui, lobby: &Lobby) {
+    if lobby.recently_left.is_empty() {
+        return;
+    }
+
+    ui.separator();
+    ui.collapsing(
+        format!("Recently left ({})", lobby.recently_left.len()),
+        |ui| {
+            Grid::new("recently_left").striped(true).show(ui, |ui| {
+                for left in lobby.recently_left.iter().rev() {
+                    let player = &left.player;
+                    ui.label(&player.name);
+                    ui.label(left.left.format("%H:%M").to_string());
+                    ui.label(format!("{}/{}", player.kills, player.deaths))
+                        .on_hover_text("Kills/Deaths");
+                    ui.colored_label(Color32::GRAY, &left.reason);
+                    ui.end_row();
+                }
+            });
+        },
+    );
+}
+
+/// Server name, map and player counts from the status output
+fn add_server_info(ui: &mut Ui, lobby: &Lobby) {
+    let server = &lobby.server;
+    if server.hostname.is_empty() && server.map.is_empty() {
+        return;
+    }
+
+    ui.horizontal(|ui| {
+        ui.strong(&server.hostname)
+            .on_hover_text(format!("{}\n{}", server.address, server.steamid));
+
+        let mut map = server.map.clone();
+        if let Some(started) = server.map_started {
+            map.push_str(&format!(" (since {})", started.format("%H:%M")));
+        }
+        ui.label(map);
+
+        let mut players = format!("{}/{} players", server.humans, server.max_players);
+        if server.bots > 0 {
+            players.push_str(&format!(", {} bots", server.bots));
+        }
+        ui.label(players)
+            .on_hover_text(format!("Tags: {}", server.tags.join(", ")));
+    });
+}
+
+/// The current or last kick vote, and the vote cooldown
+fn add_vote_info(ui: &mut Ui, lobby: &Lobby) {
+    let now = lobby.now();
+    let cooldown = lobby.vote_cooldown_secs(now);
+    if lobby.vote.is_none() && cooldown.is_none() {
+        return;
+    }
+
+    ui.horizontal(|ui| {
+        if let Some(vote) = &lobby.vote {
+            let text = format!(
+                "{} called a vote to kick {} (yes {}, no {})",
+                vote.caller, vote.target, vote.yes, vote.no
+            );
+            let (color, state) = match &vote.result {
+                _ if vote.is_active(now) => (Color32::YELLOW, "in progress".to_string()),
+                Some(VoteResult::Passed) => (Color32::GREEN, "passed".to_string()),
+                Some(VoteResult::Failed(reason)) if !reason.is_empty() => {
+                    (Color32::GRAY, format!("failed: {}", reason))
+                }
+                Some(VoteResult::Failed(_)) => (Color32::GRAY, "failed".to_string()),
+                None => (Color32::GRAY, "no result seen".to_string()),
+            };
+            let response = ui.colored_label(
+                color,
+                format!("{} at {}: {}", text, vote.started.format("%H:%M"), state),
+            );
+            if let Some(steamid) = vote.target_steamid {
+                response.on_hover_text(format!("{} is {}", vote.target, steamid.to_steam_id32()));
+            }
+        }
+
+        if let Some(secs) = cooldown {
+            ui.colored_label(Color32::GRAY, format!("Can't call a vote for {} s", secs));
+        }
+    });
 }
 
 fn cmp_for_scoreboard(a: &Player, b: &Player) -> std::cmp::Ordering {
@@ -130,3 +233,25 @@ fn cmp_for_scoreboard(a: &Player, b: &Player) -> std::cmp::Ordering {
 
     a.name.cmp(&b.name)
 }
+
+fn cmp_by_hours(a: &Player, b: &Player) -> std::cmp::Ordering {
+    // Sort by team, hours(asc), and lastly player name.
+    // Private and not yet fetched playtimes are sorted last.
+    if a.team != b.team {
+        return a.team.cmp(&b.team);
+    }
+
+    let hours = |p: &Player| {
+        p.steam_info
+            .as_ref()
+            .and_then(|info| info.tf2_playtime)
+            .and_then(|playtime| playtime.hours())
+            .unwrap_or(u32::MAX)
+    };
+
+    if hours(a) != hours(b) {
+        return hours(a).cmp(&hours(b));
+    }
+
+    a.name.cmp(&b.name)
+}
@@ -1,21 +1,42 @@
 use std::sync::{Arc, Mutex};
 
-use super::player_tooltip::add_player_tooltip;
+use super::{
+    colors::{color_for_flag, color_for_party},
+    player_tooltip::add_player_tooltip,
+};
 use crate::{
     appbus::AppBus,
-    models::steamid::SteamID,
-    tf2::lobby::{Player, Team},
+    models::{
+        flag_description, flag_shortname,
+        player_db::{PlayerDbCommand, PlayerFlagEntry},
+        steamid::SteamID,
+        ALL_FLAGS,
+    },
+    tf2::lobby::{Lobby, LobbyCommand, Player, PlayerState, Team},
 };
-use eframe::egui::{Align, Color32, Grid, Layout, Sense, Ui, Vec2};
+use chrono::Local;
+use eframe::egui::{Align, Align2, Button, Color32, FontId, Grid, Label, Layout, Sense, Ui, Vec2};
+
+/// Accounts with fewer TF2 hours than this are highlighted
+const LOW_PLAYTIME_HOURS: u32 = 100;
+
+/// Pings lower than this are highlighted, bots often have a very low ping
+const LOW_PING_MS: u32 = 10;
+
+/// Players still spawning after being connected this long are highlighted
+const LONG_SPAWNING_SECS: i64 = 120;
 
+#[allow(clippy::too_many_arguments)]
 pub fn scoreboard_team(
     ui: &mut Ui,
     bus: &Arc<Mutex<AppBus>>,
     title: &str,
     self_steamid: SteamID,
+    lobby: &mut Lobby,
     players: &Vec<&Player>,
     team_name: &str,
     show_crits: &mut bool,
+    sort_by_hours: &mut bool,
 ) {
     ui.heading(format!("{} - {} players", title, players.len()));
 
@@ -34,6 +55,11 @@ pub fn scoreboard_team(
         if *show_crits {
             ui.colored_label(Color32::GRAY, format!("({})", total_crits_deaths));
         }
+
+        let total_captures = players.iter().map(|p| p.captures).sum::<u32>();
+        let total_defenses = players.iter().map(|p| p.defenses).sum::<u32>();
+        ui.label(format!("Captures: {}", total_captures));
+        ui.label(format!("Defenses: {}", total_defenses));
     });
 
     Grid::new(team_name).striped(true).show(ui, |ui| {
@@ -52,7 +78,28 @@ pub fn scoreboard_team(
             // ui.label(RichText::new("Deaths").strong());
             ui.label("Deaths");
         });
-        // ui.label("Flags");
+        ui.with_layout(Layout::top_down(Align::LEFT), |ui| {
+            if ui
+                .selectable_label(*sort_by_hours, "Hours")
+                .on_hover_text("TF2 hours. Click to sort by hours")
+                .clicked()
+            {
+                *sort_by_hours = !*sort_by_hours;
+            }
+        });
+        ui.with_layout(Layout::top_down(Align::LEFT), |ui| {
+            ui.label("Time")
+                .on_hover_text("Time connected to the server");
+        });
+        ui.with_layout(Layout::top_down(Align::LEFT), |ui| {
+            ui.label("Ping");
+        });
+        ui.with_layout(Layout::top_down(Align::LEFT), |ui| {
+            ui.label("Loss");
+        });
+        ui.with_layout(Layout::top_down(Align::LEFT), |ui| {
+            ui.label("Flags");
+        });
         ui.with_layout(Layout::top_down(Align::LEFT), |ui| {
             // ui.label(RichText::new("Links").strong());
             ui.label("Links");
@@ -62,9 +109,9 @@ pub fn scoreboard_team(
 
         for player in players {
             // Team color box
-            add_team_symbol(ui, self_steamid, player);
+            add_team_symbol(ui, self_steamid, lobby, player);
 
-            add_player_name(ui, player);
+            add_player_name(ui, bus, lobby, player);
 
             // Player kills
             ui.horizontal(|ui| {
@@ -82,29 +129,86 @@ pub fn scoreboard_team(
                 }
             });
 
-            // add_flags(ui, &player);
+            add_playtime(ui, player);
+            add_connection(ui, player);
+
+            add_flags(ui, bus, self_steamid, lobby, player);
             add_links(ui, player);
-            add_vote(ui, bus, player);
+            add_vote(ui, bus, lobby, player);
 
             ui.end_row();
         }
     });
 }
 
-fn add_player_name(ui: &mut Ui, player: &Player) {
+fn add_player_name(ui: &mut Ui, bus: &Arc<Mutex<AppBus>>, lobby: &Lobby, player: &Player) {
     // Player icon and name
     ui.horizontal(|ui| {
         if let Some(steam_info) = &player.steam_info {
             ui.image(&steam_info.avatar)
-                .on_hover_ui(|ui| add_player_tooltip(ui, player));
+                .on_hover_ui(|ui| add_player_tooltip(ui, lobby, player));
+        }
+
+        // Right click for the context menu
+        ui.add(Label::new(player.name.clone()).sense(Sense::click()))
+            .on_hover_ui(|ui| add_player_tooltip(ui, lobby, player))
+            .context_menu(|ui| {
+                if ui
+                    .button("Refresh Steam info")
+                    .on_hover_text("Fetch the player's Steam info again, skipping the cache")
+                    .clicked()
+                {
+                    bus.lock()
+                        .unwrap()
+                        .send_lobby_cmd(LobbyCommand::RefreshSteamInfo {
+                            steamid: player.steamid,
+                        });
+                    ui.close_menu();
+                }
+            });
+
+        if !player.same_name_as.is_empty() {
+            add_impostor_marker(ui, lobby, player);
         }
 
-        ui.label(player.name.clone())
-            .on_hover_ui(|ui| add_player_tooltip(ui, player));
+        if player.renames > 0 {
+            ui.colored_label(Color32::GRAY, format!("✎{}", player.renames))
+                .on_hover_text(format!("Renamed {} times", player.renames));
+        }
     });
 }
 
-fn add_team_symbol(ui: &mut Ui, self_steamid: SteamID, player: &Player) {
+/// Shown on all players with the same looking name
+fn add_impostor_marker(ui: &mut Ui, lobby: &Lobby, player: &Player) {
+    let names: Vec<String> = player
+        .same_name_as
+        .iter()
+        .filter_map(|steamid| lobby.get_player(None, Some(*steamid)))
+        .map(|other| format!("{} ({})", other.name, other.steamid.to_u64()))
+        .collect();
+
+    let (color, text) = if player.suspected_impostor {
+        (
+            super::colors::RED_ORANGE,
+            format!(
+                "Suspected impostor. Newer account with the same looking name as:\n{}",
+                names.join("\n")
+            ),
+        )
+    } else {
+        (
+            super::colors::ORANGE,
+            format!(
+                "A newer account has the same looking name:\n{}",
+                names.join("\n")
+            ),
+        )
+    };
+
+    ui.colored_label(color, "⚠").on_hover_text(text);
+}
+
+fn add_team_symbol(ui: &mut Ui, self_steamid: SteamID, lobby: &Lobby, player: &Player) {
     let invader_color = super::colors::TEAM_BLU_COLOR;
     let defender_color = super::colors::TEAM_RED_COLOR;
 
@@ -131,86 +235,167 @@ fn add_team_symbol(ui: &mut Ui, self_steamid: SteamID, player: &Player) {
                     let (rect, _response) = ui.allocate_at_least(size, Sense::hover());
                     ui.painter().rect_filled(rect, 3.0f32, Color32::GREEN);
                 }
+
+                if let Some(bans) = steam_info.bans.as_ref().filter(|b| b.has_bans()) {
+                    let (rect, response) = ui.allocate_at_least(size, Sense::hover());
+                    ui.painter().rect_filled(rect, 3.0f32, Color32::YELLOW);
+                    response.on_hover_text(format!(
+                        "VAC bans: {}, game bans: {}. Last ban {} days ago",
+                        bans.vac_bans, bans.game_bans, bans.days_since_last_ban
+                    ));
+                }
+            }
+
+            // Party marker, the same number and color for all friends in the party
+            if let Some(party) = player.party {
+                let (rect, response) = ui.allocate_at_least(size, Sense::hover());
+                ui.painter()
+                    .rect_filled(rect, 3.0f32, color_for_party(party));
+                ui.painter().text(
+                    rect.center(),
+                    Align2::CENTER_CENTER,
+                    party.to_string(),
+                    FontId::monospace(12.0),
+                    Color32::BLACK,
+                );
+
+                let members: Vec<&str> = lobby
+                    .players
+                    .iter()
+                    .filter(|p| p.party == Some(party))
+                    .map(|p| p.name.as_str())
+                    .collect();
+                response.on_hover_text(format!("Party {}: {}", party, members.join(", ")));
             }
         });
     });
 }
 
-// #[allow(dead_code)]
-// fn add_flags(ui: &mut Ui, player: &Player) {
-//     ui.horizontal_wrapped(|ui| {
-//         ui.set_max_width(140.0);
-
-//         let flags = vec![
-//             PlayerFlags::Cheater,
-//             PlayerFlags::Bot,
-//             PlayerFlags::Sus,
-//             PlayerFlags::New,
-//             PlayerFlags::Racist,
-//             PlayerFlags::Exploiter,
-//         ];
-
-//         for flag in flags {
-//             let is_active = player.flags.contains(&flag);
-
-//             let (fgcolor, bgcolor) = if is_active {
-//                 color_for_flag(flag)
-//             } else {
-//                 (Color32::WHITE, Color32::DARK_GRAY)
-//             };
-
-//             let text = flag_shortname(flag);
-//             let tooltip = format!("{}. Click to toggle", flag_description(flag));
-
-//             ui.scope(|ui| {
-//                 ui.style_mut().visuals.override_text_color = Some(fgcolor);
-
-//                 // ui.style_mut().
-//                 ui.style_mut().visuals.widgets.active.fg_stroke.color = fgcolor;
-//                 ui.style_mut().visuals.widgets.active.weak_bg_fill = bgcolor;
-//                 ui.style_mut().visuals.widgets.inactive.fg_stroke.color = fgcolor;
-//                 ui.style_mut().visuals.widgets.inactive.weak_bg_fill = bgcolor;
-//                 ui.style_mut().visuals.widgets.hovered.fg_stroke.color = fgcolor;
-//                 ui.style_mut().visuals.widgets.hovered.weak_bg_fill = bgcolor;
-
-//                 if ui.button(text).on_hover_text(tooltip).clicked() {
-//                     if is_active {
-//                         log::trace!("Removing flag: {:?}", flag);
-//                         // player.flags.retain(|&f| f != flag);
-//                     } else {
-//                         log::trace!("Adding flag: {:?}", flag);
-//                         // player.flags.push(flag);
-//                     }
-//                 }
-//             });
-
-//             // let frame = egui::containers::Frame::default()
-//             //     .fill(bgcolor)
-//             //     .stroke(egui::Stroke::new(1.0, Color32::BLACK))
-//             //     .inner_margin(4.0)
-//             //     .rounding(3.0);
-
-//             // frame.show(ui, |ui| {
-//             //     ui.scope(|ui| {
-//             //         ui.style_mut().wrap = Some(false);
-//             //         let rich_text = RichText::new(text).color(fgcolor).strong();
-
-//             //         ui.label(rich_text).on_hover_text_at_pointer(tooltip)
-//             //     });
-//             // });
-
-//             //     Frame::default()
-//             //         .stroke(ui.visuals().widgets.noninteractive.bg_stroke)
-//             //         .rounding(ui.visuals().widgets.noninteractive.rounding)
-//             //         .show(ui, |ui| {
-//             //             self.frame.show(ui, |ui| {
-//             //                 ui.style_mut().wrap = Some(false);
-//             //                 ui.label(egui::RichText::new("Content").color(egui::Color32::WHITE));
-//             //             });
-//             //         });
-//         }
-//     });
-// }
+/// Connected time, ping and loss columns
+fn add_connection(ui: &mut Ui, player: &Player) {
+    ui.horizontal(|ui| {
+        ui.label(player.connected_str())
+            .on_hover_text(format!("Joined at {}", player.joined.format("%H:%M:%S")));
+        if player.state == PlayerState::Spawning {
+            let color = if player.connected.num_seconds() >= LONG_SPAWNING_SECS {
+                super::colors::RED_ORANGE
+            } else {
+                Color32::GRAY
+            };
+            ui.colored_label(color, "spawning");
+        }
+    });
+
+    match player.ping {
+        Some(ping) if ping < LOW_PING_MS => {
+            ui.colored_label(super::colors::RED_ORANGE, format!("{:3}", ping))
+                .on_hover_text("Very low ping");
+        }
+        Some(ping) => {
+            ui.label(format!("{:3}", ping));
+        }
+        None => {
+            ui.colored_label(Color32::GRAY, "  -")
+                .on_hover_text("Not in the status output yet");
+        }
+    }
+
+    ui.label(format!("{:3}", player.loss));
+}
+
+fn add_flags(
+    ui: &mut Ui,
+    bus: &Arc<Mutex<AppBus>>,
+    self_steamid: SteamID,
+    lobby: &mut Lobby,
+    player: &Player,
+) {
+    ui.horizontal_wrapped(|ui| {
+        ui.set_max_width(140.0);
+
+        for flag in ALL_FLAGS {
+            // Only your own flags can be toggled, flags from subscribed lists are shown as active
+            let is_local = player.flags.iter().any(|f| f.flag == flag && f.is_local());
+            let is_active = player.has_flag(flag);
+
+            let (fgcolor, bgcolor) = if is_active {
+                color_for_flag(flag)
+            } else {
+                (Color32::WHITE, Color32::DARK_GRAY)
+            };
+
+            let text = flag_shortname(flag);
+            let mut tooltip = format!("{}. Click to toggle", flag_description(flag));
+            for source in player.flags.iter().filter(|f| f.flag == flag) {
+                if let Some(source) = &source.source {
+                    tooltip.push_str(&format!("\nFlagged by playerlist '{}'", source));
+                }
+            }
+
+            ui.scope(|ui| {
+                ui.style_mut().visuals.override_text_color = Some(fgcolor);
+
+                ui.style_mut().visuals.widgets.active.fg_stroke.color = fgcolor;
+                ui.style_mut().visuals.widgets.active.weak_bg_fill = bgcolor;
+                ui.style_mut().visuals.widgets.inactive.fg_stroke.color = fgcolor;
+                ui.style_mut().visuals.widgets.inactive.weak_bg_fill = bgcolor;
+                ui.style_mut().visuals.widgets.hovered.fg_stroke.color = fgcolor;
+                ui.style_mut().visuals.widgets.hovered.weak_bg_fill = bgcolor;
+
+                if ui.button(text).on_hover_text(tooltip).clicked() {
+                    log::trace!("Setting flag {:?} to {}", flag, !is_local);
+                    bus.lock()
+                        .unwrap()
+                        .send_player_db_cmd(PlayerDbCommand::SetFlag {
+                            steamid: player.steamid,
+                            flag,
+                            active: !is_local,
+                        });
+
+                    // Update our copy of the lobby right away, the lobby thread
+                    // will send the stored flags in its next report.
+                    if let Some(player) = lobby.get_player_mut(None, Some(player.steamid)) {
+                        if is_local {
+                            player.flags.retain(|f| f.flag != flag || !f.is_local());
+                        } else {
+                            player.flags.push(PlayerFlagEntry {
+                                flag,
+                                added: Local::now(),
+                                added_by: self_steamid.to_u64().to_string(),
+                                source: None,
+                            });
+                        }
+                    }
+                }
+            });
+        }
+    });
+}
+
+fn add_playtime(ui: &mut Ui, player: &Player) {
+    let playtime = player
+        .steam_info
+        .as_ref()
+        .and_then(|steam_info| steam_info.tf2_playtime);
+
+    match playtime {
+        Some(playtime) => match playtime.hours() {
+            Some(hours) if hours < LOW_PLAYTIME_HOURS => {
+                ui.colored_label(super::colors::RED_ORANGE, format!("{:5}", hours))
+                    .on_hover_text("Few hours in TF2");
+            }
+            Some(hours) => {
+                ui.label(format!("{:5}", hours));
+            }
+            None => {
+                ui.colored_label(Color32::GRAY, "private");
+            }
+        },
+        None => {
+            ui.label("");
+        }
+    }
+}
 
 fn add_links(ui: &mut Ui, player: &Player) {
     fn make_link(ui: &mut Ui, url: String, text: &str) {
@@ -230,11 +415,23 @@ fn add_links(ui: &mut Ui, player: &Player) {
     });
 }
 
-fn add_vote(ui: &mut Ui, bus: &Arc<Mutex<AppBus>>, player: &&Player) {
+fn add_vote(ui: &mut Ui, bus: &Arc<Mutex<AppBus>>, lobby: &Lobby, player: &&Player) {
+    let now = lobby.now();
+    let blocked = if lobby.vote_in_progress(now) {
+        Some("A vote is already in progress".to_string())
+    } else {
+        lobby
+            .vote_cooldown_secs(now)
+            .map(|secs| format!("You can call a vote in {} seconds", secs))
+    };
+
     ui.horizontal(|ui| {
         ui.menu_button("☰ Vote", |ui| {
             ui.heading(format!("Kick {}", player.name));
-            if ui.button("Cheating").clicked() {
+            let button = ui.add_enabled(blocked.is_none(), Button::new("Cheating"));
+            if let Some(blocked) = &blocked {
+                button.on_disabled_hover_text(blocked);
+            } else if button.clicked() {
                 log::info!("Vote to kick player '{}' for cheating", player.name);
                 let cmd = format!("callvote kick \"{} cheating\"", player.id);
                 bus.lock().unwrap().send_rcon_cmd(cmd.as_str());
@@ -1,21 +1,45 @@
 mod appbus;
 mod gui;
 mod models;
+#[cfg(test)]
+mod test_util;
 mod tf2;
 mod utils;
 
 use appbus::AppBus;
 use models::app_settings::AppSettings;
 use std::sync::{Arc, Mutex};
+use tf2::logfile::logfile_replay::{self, ReplayOptions};
 
 fn main() -> Result<(), eframe::Error> {
     simple_logger::SimpleLogger::new().init().unwrap();
 
-    let settings = AppSettings::load_or_default();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let replay = match ReplayOptions::from_args(&args) {
+        Ok(replay) => replay,
+        Err(error) => {
+            eprintln!("{}\n{}", error, logfile_replay::USAGE);
+            std::process::exit(2);
+        }
+    };
+
     let bus = Arc::new(Mutex::new(AppBus::default()));
 
-    tf2::start(&settings, &bus);
+    let Some(replay) = replay else {
+        let settings = AppSettings::load_or_default();
+        tf2::start(&settings, &bus);
+        return gui::run(&settings, &bus);
+    };
+
+    let settings = AppSettings::load_for_replay();
+    let replay_thread_handle = tf2::start_replay(&settings, &bus, &replay);
+
+    if replay.headless {
+        logfile_replay::run_headless(&bus, replay_thread_handle);
+        return Ok(());
+    }
 
+    bus.lock().unwrap().logfile_watcher_thread_handle = Some(replay_thread_handle);
     gui::run(&settings, &bus)
 }
 
@@ -31,7 +55,7 @@ fn main() -> Result<(), eframe::Error> {
 // fn test_steam_api(settings: &AppSettings) {
 //     use crate::models::steamid::SteamID;
 
-//     let mut steam_api = tf2::steam::SteamApi::new(settings);
+//     let mut steam_api = tf2::steam::SteamWebApi::new(settings);
 
 //     // 76561199119901587,76561199289898291,76561199234573637,76561199191957545,76561199243399574,76561198064076891,76561199197733316,76561199174058886,76561198076719730,76561198884330277,76561199156370643,76561198090231678,76561198899495757,76561198147668557,76561198370670319,76561199467708430,76561198080281312,76561199379427015,76561198451145010,76561199400218364,76561199559924950,76561198999562072,76561199664152002,76561197974228301
 //     let steamid64 = models::steamid::SteamID::from_steam_id32("[U:1:169802]");
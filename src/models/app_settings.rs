@@ -1,5 +1,5 @@
 use super::steamid::SteamID;
-use crate::utils::BoxResult;
+use crate::{tf2::steam::client::DEFAULT_STEAM_API_URL, utils::BoxResult};
 use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::prelude::*;
@@ -20,10 +20,86 @@ pub struct AppSettings {
     /// Go here to crate a new key: https://steamcommunity.com/dev/apikey
     pub steam_api_key: String,
 
+    /// Base URL of the Steam Web API
+    #[serde(default = "default_steam_api_url")]
+    pub steam_api_url: String,
+
+    /// Max number of Steam API calls a day. Steam allows 100,000 calls a day per key.
+    #[serde(default = "default_steam_api_daily_budget")]
+    pub steam_api_daily_budget: u32,
+
     /// TF2 RCON settings
     pub rcon_password: String,
     pub rcon_ip: String,
     pub rcon_port: u16,
+
+    /// Third party playerlists in TF2 Bot Detector format to subscribe to
+    #[serde(default)]
+    pub playerlist_sources: Vec<PlayerlistSource>,
+
+    /// How long replies from the Steam API are cached
+    #[serde(default)]
+    pub steam_cache: SteamCacheSettings,
+}
+
+/// A playerlist that is fetched from an URL or read from a file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerlistSource {
+    /// Shown in the player tooltip for flags from this list
+    pub name: String,
+
+    /// http(s) URL or path to a local file
+    pub location: String,
+
+    /// How often the list is fetched again
+    #[serde(default = "default_refresh_minutes")]
+    pub refresh_minutes: u64,
+}
+
+fn default_refresh_minutes() -> u64 {
+    60
+}
+
+fn default_steam_api_url() -> String {
+    DEFAULT_STEAM_API_URL.to_string()
+}
+
+fn default_steam_api_daily_budget() -> u32 {
+    50_000
+}
+
+/// Hours until each kind of cached Steam API reply is fetched again.
+/// Zero means it's always fetched.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct SteamCacheSettings {
+    /// Name, avatar and account age
+    pub summaries_hours: u64,
+    pub bans_hours: u64,
+    /// TF2 hours
+    pub playtime_hours: u64,
+    pub friend_lists_hours: u64,
+}
+
+impl Default for SteamCacheSettings {
+    fn default() -> Self {
+        Self {
+            summaries_hours: 24,
+            bans_hours: 24,
+            playtime_hours: 7 * 24,
+            friend_lists_hours: 3 * 24,
+        }
+    }
+}
+
+impl SteamCacheSettings {
+    /// The longest of the TTLs
+    pub fn max_hours(&self) -> u64 {
+        self.summaries_hours
+            .max(self.bans_hours)
+            .max(self.playtime_hours)
+            .max(self.friend_lists_hours)
+    }
 }
 
 impl Default for AppSettings {
@@ -37,10 +113,16 @@ impl Default for AppSettings {
             self_steamid64,
 
             steam_api_key: "".to_string(),
+            steam_api_url: default_steam_api_url(),
+            steam_api_daily_budget: default_steam_api_daily_budget(),
 
             rcon_password: "rconpwd".to_string(),
             rcon_ip: "127.0.0.1".to_string(),
             rcon_port: 40434,
+
+            playerlist_sources: Vec::new(),
+
+            steam_cache: SteamCacheSettings::default(),
         }
     }
 }
@@ -81,6 +163,26 @@ impl AppSettings {
     /// If the file does not exist, return the error
     /// If the file exists but is invalid, log a warning and exit the application.
     pub fn load() -> BoxResult<AppSettings> {
+        let settings = Self::read_file()?;
+
+        if !settings.validate_settings() {
+            log::info!("Settings are not valid.");
+            exit(1);
+        }
+
+        Ok(settings)
+    }
+
+    /// Settings for replaying a log. Replays need no TF2 or RCON, so
+    /// those settings are not checked, and a missing file is not an error.
+    pub fn load_for_replay() -> Self {
+        Self::read_file().unwrap_or_else(|error| {
+            log::warn!("Error loading settings file: {}. Using defaults.", error);
+            AppSettings::default()
+        })
+    }
+
+    fn read_file() -> BoxResult<AppSettings> {
         let mut f = File::open(SETTINGS_FILENAME)?;
         let mut json = String::new();
         f.read_to_string(&mut json)?;
@@ -89,11 +191,6 @@ impl AppSettings {
         log::info!("Settings loaded from file {}", SETTINGS_FILENAME);
         log::info!("\n{}", json);
 
-        if !settings.validate_settings() {
-            log::info!("Settings are not valid.");
-            exit(1);
-        }
-
         Ok(settings)
     }
 
@@ -155,19 +252,22 @@ fn get_exe_filename() -> String {
 
 #[cfg(target_os = "linux")]
 fn get_log_filename() -> String {
-    std::fs::canonicalize(r"~/.local/share/Steam/steamapps/common/Team Fortress 2/tf/console.log")
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .to_string()
+    linux_tf2_path("tf/console.log")
 }
 
 #[cfg(target_os = "linux")]
 fn get_exe_filename() -> String {
-    std::fs::canonicalize(r"~/.local/share/Steam/steamapps/common/Team Fortress 2/hl2_linux")
-        .unwrap()
-        .to_str()
-        .unwrap()
+    linux_tf2_path("hl2_linux")
+}
+
+/// Paths starting with ~ can't be canonicalized, so the home directory is used instead
+#[cfg(target_os = "linux")]
+fn linux_tf2_path(filename: &str) -> String {
+    let home = std::env::var("HOME").unwrap_or_default();
+    Path::new(&home)
+        .join(".local/share/Steam/steamapps/common/Team Fortress 2")
+        .join(filename)
+        .to_string_lossy()
         .to_string()
 }
 
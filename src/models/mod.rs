@@ -1,9 +1,12 @@
 pub mod app_settings;
+pub mod player_db;
 pub mod steamid;
+pub mod tf2bd_playerlist;
 
 use self::{app_settings::AppSettings, steamid::SteamID};
 use crate::{appbus::AppBus, tf2::lobby::Lobby};
 use bus::BusReader;
+use serde::{Deserialize, Serialize};
 use std::sync::{Arc, Mutex};
 
 pub struct AppWin {
@@ -15,6 +18,12 @@ pub struct AppWin {
     pub self_steamid: SteamID,
     pub swap_team_colors: bool,
     pub show_crits: bool,
+
+    /// Sort the scoreboard by TF2 hours instead of by kills
+    pub sort_by_hours: bool,
+
+    /// Path of the TF2 Bot Detector playerlist to import or export
+    pub playerlist_filename: String,
 }
 
 impl AppWin {
@@ -26,6 +35,8 @@ impl AppWin {
             lobby_report_bus_rx: bus.lock().unwrap().lobby_report_bus.add_rx(),
             swap_team_colors: false,
             show_crits: false,
+            sort_by_hours: false,
+            playerlist_filename: "playerlist.tf2monitor.json".to_string(),
             self_steamid: settings.self_steamid64,
         }
     }
@@ -37,7 +48,7 @@ impl AppWin {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum PlayerFlags {
     Cheater,
     Bot,
@@ -46,6 +57,15 @@ pub enum PlayerFlags {
     Exploiter,
 }
 
+/// All flags, in the order they are shown in the scoreboard
+pub const ALL_FLAGS: [PlayerFlags; 5] = [
+    PlayerFlags::Cheater,
+    PlayerFlags::Bot,
+    PlayerFlags::Sus,
+    PlayerFlags::Toxic,
+    PlayerFlags::Exploiter,
+];
+
 pub fn flag_shortname(flag: PlayerFlags) -> &'static str {
     match flag {
         PlayerFlags::Cheater => "C",
@@ -0,0 +1,304 @@
+use super::{steamid::SteamID, PlayerFlags};
+use crate::utils::BoxResult;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+
+/// Stored in the same directory as the settings.json file
+const PLAYER_DB_FILENAME: &str = "players.json";
+
+/// A flag set on a player, with info about when and by whom it was set.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerFlagEntry {
+    pub flag: PlayerFlags,
+    pub added: DateTime<Local>,
+
+    /// Who added the flag. For flags set in the scoreboard this is your own SteamID64.
+    pub added_by: String,
+
+    /// Name of the subscribed playerlist the flag came from. None for your own flags.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+/// Everything we know about a player we have flagged or made notes about.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerRecord {
+    pub steamid: SteamID,
+
+    /// The name the player had the last time we saw them
+    pub last_name: String,
+
+    #[serde(default)]
+    pub flags: Vec<PlayerFlagEntry>,
+
+    #[serde(default)]
+    pub notes: String,
+}
+
+/// The on disk format of the player database
+#[derive(Serialize, Deserialize, Default)]
+struct PlayerDbFile {
+    players: Vec<PlayerRecord>,
+}
+
+/// Changes to the player database, sent from the GUI to the lobby thread.
+#[derive(Debug, Clone)]
+pub enum PlayerDbCommand {
+    /// Set or clear a flag on a player
+    SetFlag {
+        steamid: SteamID,
+        flag: PlayerFlags,
+        active: bool,
+    },
+
+    /// Merge a TF2 Bot Detector playerlist file into the database
+    ImportTf2bd { filename: String },
+
+    /// Write the database as a TF2 Bot Detector playerlist file
+    ExportTf2bd { filename: String },
+}
+
+/// The local database of players, keyed by SteamID.
+/// It's persisted as JSON in the players.json file.
+#[derive(Debug, Default)]
+pub struct PlayerDb {
+    filename: String,
+    players: HashMap<SteamID, PlayerRecord>,
+}
+
+impl PlayerRecord {
+    pub fn new(steamid: SteamID, name: &str) -> Self {
+        Self {
+            steamid,
+            last_name: name.to_string(),
+            flags: Vec::new(),
+            notes: String::new(),
+        }
+    }
+
+    pub fn has_flag(&self, flag: PlayerFlags) -> bool {
+        self.flags.iter().any(|f| f.flag == flag)
+    }
+}
+
+impl PlayerFlagEntry {
+    /// True if the flag was set by you, and not by a subscribed playerlist
+    pub fn is_local(&self) -> bool {
+        self.source.is_none()
+    }
+}
+
+impl PlayerDb {
+    /// Tries to load the players.json file from the current directory.
+    /// If the file don't exist or can't be read, start with an empty database.
+    pub fn load_or_default() -> Self {
+        match Self::load(PLAYER_DB_FILENAME) {
+            Ok(db) => db,
+            Err(error) => {
+                log::warn!(
+                    "Could not load player database {}: {}. Starting with an empty one.",
+                    PLAYER_DB_FILENAME,
+                    error
+                );
+
+                Self {
+                    filename: PLAYER_DB_FILENAME.to_string(),
+                    players: HashMap::new(),
+                }
+            }
+        }
+    }
+
+    /// Loads the players.json file like load_or_default(), but never writes it back.
+    /// For replaying a saved log, which must not rename or flag the players in the file.
+    pub fn load_read_only() -> Self {
+        let mut db = Self::load_or_default();
+        db.filename.clear();
+        db
+    }
+
+    pub fn load(filename: &str) -> BoxResult<Self> {
+        let mut f = File::open(filename)?;
+        let mut json = String::new();
+        f.read_to_string(&mut json)?;
+        let file: PlayerDbFile = serde_json::from_str(&json)?;
+
+        let players: HashMap<SteamID, PlayerRecord> = file
+            .players
+            .into_iter()
+            .map(|record| (record.steamid, record))
+            .collect();
+
+        log::info!(
+            "Loaded {} players from player database {}",
+            players.len(),
+            filename
+        );
+
+        Ok(Self {
+            filename: filename.to_string(),
+            players,
+        })
+    }
+
+    /// Writes the database to its file. A default database has no file and is not saved.
+    pub fn save(&self) {
+        if self.filename.is_empty() {
+            return;
+        }
+
+        if let Err(error) = self.save_internal() {
+            log::error!(
+                "Could not save player database {}: {}",
+                self.filename,
+                error
+            );
+        }
+    }
+
+    fn save_internal(&self) -> BoxResult<()> {
+        let mut players: Vec<&PlayerRecord> = self.players.values().collect();
+        players.sort_by_key(|record| record.steamid.to_u64());
+
+        let json = serde_json::to_string_pretty(&serde_json::json!({ "players": players }))?;
+        let mut f = File::create(&self.filename)?;
+        f.write_all(json.as_bytes())?;
+
+        Ok(())
+    }
+
+    pub fn get(&self, steamid: SteamID) -> Option<&PlayerRecord> {
+        self.players.get(&steamid)
+    }
+
+    /// Returns the record for a player, adding the player to the database if needed.
+    pub fn get_or_insert(&mut self, steamid: SteamID, name: &str) -> &mut PlayerRecord {
+        self.players
+            .entry(steamid)
+            .or_insert_with(|| PlayerRecord::new(steamid, name))
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = &PlayerRecord> {
+        self.players.values()
+    }
+
+    /// Sets or clears a flag on a player. The player is added to the database if needed.
+    /// Returns true if the database was changed.
+    pub fn set_flag(
+        &mut self,
+        steamid: SteamID,
+        name: &str,
+        flag: PlayerFlags,
+        active: bool,
+        added_by: &str,
+    ) -> bool {
+        if active {
+            return self.add_flag(steamid, name, flag, Local::now(), added_by);
+        }
+
+        let record = self.get_or_insert(steamid, name);
+        if !record.has_flag(flag) {
+            return false;
+        }
+
+        record.flags.retain(|f| f.flag != flag);
+        true
+    }
+
+    /// Adds a flag that was set at the given time, e.g. from an imported playerlist.
+    /// Returns false if the player has the flag already.
+    pub fn add_flag(
+        &mut self,
+        steamid: SteamID,
+        name: &str,
+        flag: PlayerFlags,
+        added: DateTime<Local>,
+        added_by: &str,
+    ) -> bool {
+        let record = self.get_or_insert(steamid, name);
+        if record.has_flag(flag) {
+            return false;
+        }
+
+        record.flags.push(PlayerFlagEntry {
+            flag,
+            added,
+            added_by: added_by.to_string(),
+            source: None,
+        });
+        true
+    }
+
+    /// Remembers the latest name of a player, if the player is in the database.
+    /// Returns true if the database was changed.
+    pub fn update_name(&mut self, steamid: SteamID, name: &str) -> bool {
+        match self.players.get_mut(&steamid) {
+            Some(record) if record.last_name != name => {
+                record.last_name = name.to_string();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_flag() {
+        let mut db = PlayerDb::default();
+        let steamid = SteamID::from_u64(76561197960435530);
+
+        assert!(db.set_flag(steamid, "Player1", PlayerFlags::Bot, true, "me"));
+        assert!(!db.set_flag(steamid, "Player1", PlayerFlags::Bot, true, "me"));
+        assert!(db.set_flag(steamid, "Player1", PlayerFlags::Cheater, true, "me"));
+
+        let record = db.get(steamid).unwrap();
+        assert_eq!(record.last_name, "Player1");
+        assert!(record.has_flag(PlayerFlags::Bot));
+        assert!(record.has_flag(PlayerFlags::Cheater));
+        assert_eq!(record.flags[0].added_by, "me");
+
+        assert!(db.set_flag(steamid, "Player1", PlayerFlags::Bot, false, "me"));
+        assert!(!db.get(steamid).unwrap().has_flag(PlayerFlags::Bot));
+    }
+
+    #[test]
+    fn test_update_name() {
+        let mut db = PlayerDb::default();
+        let steamid = SteamID::from_u64(76561197960435530);
+
+        // Players not in the database are not added
+        assert!(!db.update_name(steamid, "Player1"));
+        assert!(db.get(steamid).is_none());
+
+        db.set_flag(steamid, "Player1", PlayerFlags::Sus, true, "me");
+        assert!(db.update_name(steamid, "Player2"));
+        assert_eq!(db.get(steamid).unwrap().last_name, "Player2");
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let filename = std::env::temp_dir().join("tf2monitor_test_players.json");
+        let filename = filename.to_str().unwrap();
+
+        let steamid = SteamID::from_u64(76561197960435530);
+        let mut db = PlayerDb {
+            filename: filename.to_string(),
+            players: HashMap::new(),
+        };
+        db.set_flag(steamid, "Player1", PlayerFlags::Toxic, true, "me");
+        db.players.get_mut(&steamid).unwrap().notes = "Spams voice chat".to_string();
+        db.save();
+
+        let loaded = PlayerDb::load(filename).unwrap();
+        let _ = std::fs::remove_file(filename);
+
+        assert_eq!(loaded.get(steamid), db.get(steamid));
+    }
+}
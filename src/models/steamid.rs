@@ -31,6 +31,11 @@ impl SteamID {
         self.0
     }
 
+    /// Formats the SteamID like "[U:1:169802]"
+    pub fn to_steam_id32(self) -> String {
+        format!("[U:1:{}]", self.0.saturating_sub(76561197960265728))
+    }
+
     pub fn is_valid(self) -> bool {
         self.0 >= 76561197960265728
     }
@@ -71,4 +76,16 @@ mod tests {
             SteamID::from_u64(76561199179248685)
         );
     }
+
+    #[test]
+    fn test_to_steamid32() {
+        assert_eq!(
+            SteamID::from_u64(76561197960435530).to_steam_id32(),
+            "[U:1:169802]"
+        );
+        assert_eq!(
+            SteamID::from_steam_id32("[U:1:1218982957]").to_steam_id32(),
+            "[U:1:1218982957]"
+        );
+    }
 }
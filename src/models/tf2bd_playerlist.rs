@@ -0,0 +1,392 @@
+// Import and export of TF2 Bot Detector playerlist files, e.g. playerlist.official.json
+// Schema: https://raw.githubusercontent.com/PazerOP/tf2_bot_detector/master/schemas/v3/playerlist.schema.json
+//
+// TF2BD attributes are mapped to our flags like this:
+// - cheater    <-> Cheater
+// - suspicious <-> Sus
+// - exploiter  <-> Exploiter
+// - racist     <-> Toxic
+// - bot         -> Bot. Not part of the schema, but used by some lists.
+//   TF2BD has no bot attribute, so Bot is exported as cheater.
+
+use super::{player_db::PlayerDb, steamid::SteamID, PlayerFlags};
+use crate::utils::BoxResult;
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::prelude::*;
+
+pub const TF2BD_PLAYERLIST_SCHEMA: &str =
+    "https://raw.githubusercontent.com/PazerOP/tf2_bot_detector/master/schemas/v3/playerlist.schema.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Tf2bdPlayerlist {
+    #[serde(rename = "$schema", default)]
+    pub schema: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_info: Option<Tf2bdFileInfo>,
+
+    #[serde(default)]
+    pub players: Vec<Tf2bdPlayer>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Tf2bdFileInfo {
+    #[serde(default)]
+    pub authors: Vec<String>,
+
+    #[serde(default)]
+    pub description: String,
+
+    #[serde(default)]
+    pub title: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Tf2bdPlayer {
+    /// Either a SteamID32 string like "[U:1:169802]" or a SteamID64 as number or string
+    pub steamid: Value,
+
+    #[serde(default)]
+    pub attributes: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<Tf2bdLastSeen>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub proof: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Tf2bdLastSeen {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub player_name: Option<String>,
+
+    /// Unix timestamp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<i64>,
+}
+
+impl Tf2bdPlayerlist {
+    pub fn load(filename: &str) -> BoxResult<Self> {
+        let mut f = File::open(filename)?;
+        let mut json = String::new();
+        f.read_to_string(&mut json)?;
+
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    pub fn save(&self, filename: &str) -> BoxResult<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        let mut f = File::create(filename)?;
+        f.write_all(json.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// The title from the file_info section, if there is one
+    pub fn title(&self) -> Option<&str> {
+        self.file_info
+            .as_ref()
+            .map(|info| info.title.as_str())
+            .filter(|title| !title.is_empty())
+    }
+}
+
+pub fn flag_from_attribute(attribute: &str) -> Option<PlayerFlags> {
+    match attribute.to_lowercase().as_str() {
+        "cheater" => Some(PlayerFlags::Cheater),
+        "suspicious" => Some(PlayerFlags::Sus),
+        "exploiter" => Some(PlayerFlags::Exploiter),
+        "racist" => Some(PlayerFlags::Toxic),
+        "bot" => Some(PlayerFlags::Bot),
+        _ => None,
+    }
+}
+
+pub fn attribute_from_flag(flag: PlayerFlags) -> &'static str {
+    match flag {
+        PlayerFlags::Cheater => "cheater",
+        PlayerFlags::Bot => "cheater",
+        PlayerFlags::Sus => "suspicious",
+        PlayerFlags::Toxic => "racist",
+        PlayerFlags::Exploiter => "exploiter",
+    }
+}
+
+fn parse_steamid(value: &Value) -> Option<SteamID> {
+    match value {
+        Value::Number(n) => n.as_u64().map(SteamID::from_u64),
+        Value::String(s) if s.starts_with("[U:1:") && s.ends_with(']') => {
+            // Account numbers are 32 bits. from_steam_id32 panics or overflows on
+            // anything else, and the lists come from other people.
+            s[5..s.len() - 1]
+                .parse::<u32>()
+                .ok()
+                .map(|account| SteamID::from_u64(u64::from(account) + 76561197960265728))
+        }
+        Value::String(s) => SteamID::from_u64_string(s),
+        _ => None,
+    }
+    .filter(|steamid| steamid.is_valid())
+}
+
+/// Loads a TF2BD playerlist file and merges it into the player database.
+/// Returns the number of players that were merged.
+pub fn import_playerlist(db: &mut PlayerDb, filename: &str) -> BoxResult<usize> {
+    let playerlist = Tf2bdPlayerlist::load(filename)?;
+    let added_by = playerlist.title().unwrap_or(filename).to_string();
+
+    Ok(merge_playerlist(db, &playerlist, &added_by))
+}
+
+/// Merges the players of a TF2BD playerlist into the player database.
+/// Flags are only ever added, and proofs are added to the notes.
+/// Returns the number of players that were merged.
+pub fn merge_playerlist(db: &mut PlayerDb, playerlist: &Tf2bdPlayerlist, added_by: &str) -> usize {
+    let mut count = 0;
+
+    for player in &playerlist.players {
+        let Some(steamid) = parse_steamid(&player.steamid) else {
+            log::warn!("Skipping player with bad steamid: {}", player.steamid);
+            continue;
+        };
+
+        let name = player
+            .last_seen
+            .as_ref()
+            .and_then(|last_seen| last_seen.player_name.clone())
+            .unwrap_or_default();
+
+        // The list's date is kept, so merging the list again doesn't make the flags look new
+        let added = player
+            .last_seen
+            .as_ref()
+            .and_then(|last_seen| last_seen.time)
+            .and_then(|time| Local.timestamp_opt(time, 0).single())
+            .unwrap_or_else(Local::now);
+
+        for attribute in &player.attributes {
+            match flag_from_attribute(attribute) {
+                Some(flag) => {
+                    db.add_flag(steamid, &name, flag, added, added_by);
+                }
+                None => log::debug!("Ignoring unknown TF2BD attribute '{}'", attribute),
+            }
+        }
+
+        let record = db.get_or_insert(steamid, &name);
+        if record.last_name.is_empty() {
+            record.last_name = name;
+        }
+
+        for proof in &player.proof {
+            if !record.notes.lines().any(|line| line == proof) {
+                if !record.notes.is_empty() {
+                    record.notes.push('\n');
+                }
+                record.notes.push_str(proof);
+            }
+        }
+
+        count += 1;
+    }
+
+    count
+}
+
+/// Creates a TF2BD playerlist with every player in the database that has a flag.
+pub fn playerlist_from_db(db: &PlayerDb, author: &str) -> Tf2bdPlayerlist {
+    let mut records: Vec<_> = db.records().filter(|r| !r.flags.is_empty()).collect();
+    records.sort_by_key(|record| record.steamid.to_u64());
+
+    let players = records
+        .iter()
+        .map(|record| {
+            let mut attributes: Vec<String> = vec![];
+            for entry in &record.flags {
+                let attribute = attribute_from_flag(entry.flag).to_string();
+                if !attributes.contains(&attribute) {
+                    attributes.push(attribute);
+                }
+            }
+
+            let time = record.flags.iter().map(|f| f.added.timestamp()).max();
+
+            Tf2bdPlayer {
+                steamid: Value::String(record.steamid.to_steam_id32()),
+                attributes,
+                last_seen: Some(Tf2bdLastSeen {
+                    player_name: Some(record.last_name.clone()).filter(|n| !n.is_empty()),
+                    time,
+                }),
+                proof: record.notes.lines().map(|line| line.to_string()).collect(),
+            }
+        })
+        .collect();
+
+    Tf2bdPlayerlist {
+        schema: TF2BD_PLAYERLIST_SCHEMA.to_string(),
+        file_info: Some(Tf2bdFileInfo {
+            authors: vec![author.to_string()],
+            description: "Exported from TF2Monitor".to_string(),
+            title: "TF2Monitor playerlist".to_string(),
+            update_url: None,
+        }),
+        players,
+    }
+}
+
+/// Writes every flagged player in the database to a TF2BD playerlist file.
+/// Returns the number of players that were written.
+pub fn export_playerlist(db: &PlayerDb, filename: &str, author: &str) -> BoxResult<usize> {
+    let playerlist = playerlist_from_db(db, author);
+    playerlist.save(filename)?;
+
+    Ok(playerlist.players.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_PLAYERLIST: &str = include_str!("../../testdata/playerlist.sample.json");
+
+    fn sample_db() -> PlayerDb {
+        let playerlist: Tf2bdPlayerlist = serde_json::from_str(SAMPLE_PLAYERLIST).unwrap();
+        let mut db = PlayerDb::default();
+        assert_eq!(merge_playerlist(&mut db, &playerlist, "sample"), 4);
+        db
+    }
+
+    #[test]
+    fn test_import() {
+        let db = sample_db();
+
+        // SteamID32 string
+        let record = db.get(SteamID::from_u64(76561197960435530)).unwrap();
+        assert_eq!(record.last_name, "Cheater1");
+        assert!(record.has_flag(PlayerFlags::Cheater));
+        assert_eq!(record.flags[0].added_by, "sample");
+        assert_eq!(record.flags[0].added.timestamp(), 1715176242);
+        assert_eq!(record.notes, "Aimbot in demo 1\nAimbot in demo 2");
+
+        // SteamID64 number
+        let record = db.get(SteamID::from_u64(76561199179248685)).unwrap();
+        assert!(record.has_flag(PlayerFlags::Sus));
+        assert!(record.has_flag(PlayerFlags::Toxic));
+
+        // SteamID64 string, and a player without last_seen
+        let record = db.get(SteamID::from_u64(76561197994359533)).unwrap();
+        assert!(record.has_flag(PlayerFlags::Exploiter));
+        assert_eq!(record.last_name, "");
+
+        // Non standard bot attribute, unknown attributes are ignored
+        let record = db.get(SteamID::from_u64(76561198080281312)).unwrap();
+        assert_eq!(record.flags.len(), 1);
+        assert!(record.has_flag(PlayerFlags::Bot));
+    }
+
+    #[test]
+    fn test_parse_steamid() {
+        let steamid = |s: &str| parse_steamid(&Value::String(s.to_string()));
+
+        assert_eq!(
+            steamid("[U:1:169802]"),
+            Some(SteamID::from_u64(76561197960435530))
+        );
+        assert_eq!(
+            steamid("[U:1:4294967295]"),
+            Some(SteamID::from_u64(76561202255233023))
+        );
+
+        // Out of range account numbers are skipped, not wrapped around
+        assert_eq!(steamid("[U:1:4294967296]"), None);
+        assert_eq!(steamid("[U:1:18446744073709551615]"), None);
+        assert_eq!(steamid("[U:1:-1]"), None);
+    }
+
+    #[test]
+    fn test_import_merges() {
+        let mut db = sample_db();
+        let steamid = SteamID::from_u64(76561197960435530);
+        db.set_flag(steamid, "Cheater1", PlayerFlags::Toxic, true, "me");
+
+        // Importing the same list again don't duplicate flags or proofs
+        let playerlist: Tf2bdPlayerlist = serde_json::from_str(SAMPLE_PLAYERLIST).unwrap();
+        merge_playerlist(&mut db, &playerlist, "sample");
+
+        let record = db.get(steamid).unwrap();
+        assert_eq!(record.flags.len(), 2);
+        assert!(record.has_flag(PlayerFlags::Toxic));
+        assert_eq!(record.notes, "Aimbot in demo 1\nAimbot in demo 2");
+
+        // A fresh merge, like a subscribed list that was fetched again, keeps the list's dates
+        let mut fresh = PlayerDb::default();
+        merge_playerlist(&mut fresh, &playerlist, "sample");
+        let added = |db: &PlayerDb| db.get(steamid).unwrap().flags[0].added;
+        assert_eq!(added(&fresh), added(&db));
+    }
+
+    #[test]
+    fn test_export() {
+        let db = sample_db();
+        let playerlist = playerlist_from_db(&db, "me");
+
+        assert_eq!(playerlist.schema, TF2BD_PLAYERLIST_SCHEMA);
+        assert_eq!(playerlist.file_info.as_ref().unwrap().authors, vec!["me"]);
+        assert_eq!(playerlist.players.len(), 4);
+
+        let player = &playerlist.players[0];
+        assert_eq!(player.steamid, Value::String("[U:1:169802]".to_string()));
+        assert_eq!(player.attributes, vec!["cheater"]);
+        assert_eq!(player.proof, vec!["Aimbot in demo 1", "Aimbot in demo 2"]);
+
+        // Bot is exported as cheater
+        let player = &playerlist.players[2];
+        assert_eq!(player.attributes, vec!["cheater"]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let db = sample_db();
+
+        let filename = std::env::temp_dir().join("tf2monitor_test_playerlist.json");
+        let filename = filename.to_str().unwrap();
+        assert_eq!(export_playerlist(&db, filename, "me").unwrap(), 4);
+
+        let mut imported = PlayerDb::default();
+        let count = import_playerlist(&mut imported, filename).unwrap();
+        let exported_again = playerlist_from_db(&imported, "me");
+        let _ = std::fs::remove_file(filename);
+
+        assert_eq!(count, 4);
+        for record in db.records() {
+            let other = imported.get(record.steamid).unwrap();
+            assert_eq!(other.last_name, record.last_name);
+            assert_eq!(other.notes, record.notes);
+
+            for entry in &record.flags {
+                // Bot don't survive the trip, TF2BD calls them cheaters
+                let expected = match entry.flag {
+                    PlayerFlags::Bot => PlayerFlags::Cheater,
+                    flag => flag,
+                };
+                assert!(other.has_flag(expected));
+            }
+        }
+
+        // Export -> import -> export gives the same file, except for the timestamps
+        let mut first = playerlist_from_db(&db, "me");
+        let mut second = exported_again;
+        for player in first.players.iter_mut().chain(second.players.iter_mut()) {
+            player.last_seen.as_mut().unwrap().time = None;
+        }
+        assert_eq!(first, second);
+    }
+}
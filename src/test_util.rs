@@ -0,0 +1,66 @@
+// Helpers shared by the tests of more than one module
+
+/// A local HTTP server standing in for the Steam API and the playerlist hosts
+pub mod test_server {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    /// Answers the requests in order with the given (status line, body) pairs,
+    /// and records the requests it got. Returns the base URL of the server.
+    pub fn start(responses: Vec<(&'static str, String)>) -> (String, Arc<Mutex<Vec<String>>>) {
+        start_with_headers(
+            responses
+                .into_iter()
+                .map(|(status, body)| (status, "", body))
+                .collect(),
+        )
+    }
+
+    /// Like start, with extra header lines in each response, like "ETag: \"v1\"".
+    /// The requests are recorded with their headers, one per line after the request line.
+    pub fn start_with_headers(
+        responses: Vec<(&'static str, &'static str, String)>,
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen_clone = Arc::clone(&seen);
+
+        thread::spawn(move || {
+            for (stream, (status, headers, body)) in listener.incoming().zip(responses) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut request = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                    request.push(line.trim().to_string());
+                }
+                seen_clone.lock().unwrap().push(request.join("\n"));
+
+                let headers = if headers.is_empty() {
+                    String::new()
+                } else {
+                    format!("{}\r\n", headers)
+                };
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                     {}Connection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    headers,
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        (url, seen)
+    }
+}
@@ -1,168 +1,664 @@
-use super::{Lobby, PlayerSteamInfo};
-use super::{LobbyChat, Player, PlayerKill, Team};
-use crate::tf2::steam::SteamApi;
+use super::{ChatKind, RuleMatch, Team};
+use super::{EarnedAchievement, FoundItem, LobbyChat, Player, PlayerKill, PlayerState};
+use super::{FriendList, Lobby, LobbyCommand, PlayerBans, PlayerSteamInfo, Tf2Playtime};
+use super::{Vote, VoteResult, DEFAULT_VOTE_COOLDOWN_SECS};
+use crate::tf2::steam::{
+    SteamApiFriend, SteamApiOwnedGames, SteamApiPlayer, SteamApiPlayerBans, SteamReply,
+    SteamRequest,
+};
 use crate::{
     appbus::AppBus,
-    models::{app_settings::AppSettings, steamid::SteamID},
-    tf2::logfile::LogLine,
+    models::{
+        app_settings::AppSettings,
+        player_db::{PlayerDb, PlayerDbCommand},
+        steamid::SteamID,
+        tf2bd_playerlist, PlayerFlags,
+    },
+    tf2::{
+        logfile::{KillSplit, LogLine, ServerInfoLine},
+        playerlists::SubscribedPlayerlist,
+        rules::{RuleActions, RuleEngine},
+    },
 };
 use bus::BusReader;
 use chrono::prelude::*;
 use std::{
+    collections::{BTreeMap, HashMap},
     sync::{Arc, Mutex},
-    thread::{self, sleep},
+    thread,
+    time::{Duration, Instant},
 };
 
-/// The delay between loops in run()
-const LOOP_DELAY: std::time::Duration = std::time::Duration::from_millis(5000);
+/// Players not in the status output for this long have left
+const PLAYER_TIMEOUT_SECONDS: i64 = 30;
+
+/// The lobby is sent to the GUI at most this often
+const REPORT_DELAY: Duration = Duration::from_millis(200);
+
+/// The delay between Steam API fetches in run()
+const STEAM_FETCH_DELAY: Duration = Duration::from_millis(5000);
 
 pub struct LobbyThread {
     bus: Arc<Mutex<AppBus>>,
     logfile_bus_rx: BusReader<LogLine>,
+    lobby_cmd_bus_rx: BusReader<LobbyCommand>,
+    player_db_bus_rx: BusReader<PlayerDbCommand>,
+    playerlists_bus_rx: BusReader<SubscribedPlayerlist>,
+    steam_reply_bus_rx: BusReader<SteamReply>,
     lobby: Lobby,
-    steam_api: SteamApi,
+    player_db: PlayerDb,
+
+    /// The subscribed playerlists, by source name. Kept apart from our own player database.
+    subscribed_playerlists: BTreeMap<String, PlayerDb>,
+    rules: RuleEngine,
+    self_steamid: SteamID,
+
+    /// Connected players not yet seen in status, by name
+    pending_joins: HashMap<String, DateTime<Local>>,
+
+    /// Players to refresh in the next Steam request
+    steam_refresh: Vec<SteamID>,
+
+    /// Set when replaying a saved log, see follow_log_time()
+    follow_log_time: bool,
 }
 
 /// Start the background thread for the lobby module
 pub fn start(settings: &AppSettings, bus: &Arc<Mutex<AppBus>>) -> thread::JoinHandle<()> {
-    let mut lobby_thread = LobbyThread::new(settings, bus);
+    let mut lobby_thread = LobbyThread::new(
+        settings,
+        bus,
+        PlayerDb::load_or_default(),
+        RuleEngine::load_or_default(),
+    );
 
     thread::spawn(move || lobby_thread.run())
 }
 
 impl LobbyThread {
-    pub fn new(settings: &AppSettings, bus: &Arc<Mutex<AppBus>>) -> Self {
+    /// The player database and rules are passed in, so the lobby
+    /// can be tested without the files in the current directory
+    pub fn new(
+        settings: &AppSettings,
+        bus: &Arc<Mutex<AppBus>>,
+        player_db: PlayerDb,
+        rules: RuleEngine,
+    ) -> Self {
         let logfile_bus_rx = bus.lock().unwrap().logfile_bus.add_rx();
+        let lobby_cmd_bus_rx = bus.lock().unwrap().lobby_cmd_bus.add_rx();
+        let player_db_bus_rx = bus.lock().unwrap().player_db_bus.add_rx();
+        let playerlists_bus_rx = bus.lock().unwrap().playerlists_bus.add_rx();
+        let steam_reply_bus_rx = bus.lock().unwrap().steam_reply_bus.add_rx();
         Self {
             bus: Arc::clone(bus),
             logfile_bus_rx,
+            lobby_cmd_bus_rx,
+            player_db_bus_rx,
+            playerlists_bus_rx,
+            steam_reply_bus_rx,
             lobby: Lobby::new(),
-            steam_api: SteamApi::new(settings),
+            player_db,
+            subscribed_playerlists: BTreeMap::new(),
+            rules,
+            self_steamid: settings.self_steamid64,
+            pending_joins: HashMap::new(),
+            steam_refresh: Vec::new(),
+            follow_log_time: false,
         }
     }
 
+    /// Compare the votes with the time in the log instead of the clock.
+    /// For replaying a saved log, where the clock is long past the log.
+    pub fn follow_log_time(&mut self) {
+        self.follow_log_time = true;
+    }
+
     pub fn run(&mut self) {
         log::info!("Lobby background thread started");
 
-        loop {
-            self.process_bus();
+        let mut changed = false;
+        let mut last_report = Instant::now();
+        let mut next_steam_fetch = Instant::now();
 
-            self.update_scoreboard();
+        loop {
+            // Log lines are handled as soon as they arrive
+            if let Ok(logline) = self.logfile_bus_rx.recv_timeout(REPORT_DELAY) {
+                self.process_logline(logline);
+                changed = true;
+            }
+            changed |= self.process_bus();
 
-            self.fetch_steam_info();
+            if changed && last_report.elapsed() >= REPORT_DELAY {
+                // Try again next time if the GUI is behind
+                changed = !self.update_scoreboard();
+                last_report = Instant::now();
+            }
 
-            sleep(LOOP_DELAY);
+            if Instant::now() >= next_steam_fetch {
+                self.request_steam_info();
+                next_steam_fetch = Instant::now() + STEAM_FETCH_DELAY;
+            }
         }
     }
 
-    fn process_bus(&mut self) {
-        while let Ok(cmd) = self.logfile_bus_rx.try_recv() {
-            // log::info!("LobbyThread: Got message: {:?}", cmd);
+    /// Handles everything waiting on the buses. Returns true if there was anything.
+    fn process_bus(&mut self) -> bool {
+        let mut changed = false;
+
+        while let Ok(logline) = self.logfile_bus_rx.try_recv() {
+            self.process_logline(logline);
+            changed = true;
+        }
+
+        while let Ok(cmd) = self.lobby_cmd_bus_rx.try_recv() {
+            changed = true;
             match cmd {
-                LogLine::Unknown { line: _ } => {}
-                LogLine::StatusHeader { when } => self.purge_old_players(when),
-                LogLine::StatusForPlayer {
-                    when,
-                    id,
-                    name,
-                    steam_id32,
-                } => self.player_seen(when, id, name, steam_id32),
-                LogLine::Kill {
-                    when,
-                    killer,
-                    victim,
-                    weapon,
-                    crit,
-                } => self.kill(when, killer, victim, weapon, crit),
-                LogLine::Suicide { when, name } => self.suicide(when, name),
-                LogLine::LobbyCreated { when: _when } => self.new_lobby(),
-                LogLine::LobbyDestroyed { when: _when } => {}
-                LogLine::Chat {
-                    when,
-                    name,
-                    message,
-                    dead,
-                    team,
-                } => self.chat(when, name, message, dead, team),
-                LogLine::PlayerTeam { steam_id32, team } => self.assign_team(steam_id32, team),
+                LobbyCommand::RefreshSteamInfo { steamid } => self.refresh_steam_info(steamid),
             }
         }
+
+        while let Ok(cmd) = self.player_db_bus_rx.try_recv() {
+            changed = true;
+            match cmd {
+                PlayerDbCommand::SetFlag {
+                    steamid,
+                    flag,
+                    active,
+                } => self.set_player_flag(steamid, flag, active),
+                PlayerDbCommand::ImportTf2bd { filename } => self.import_tf2bd(filename),
+                PlayerDbCommand::ExportTf2bd { filename } => self.export_tf2bd(filename),
+            }
+        }
+
+        while let Ok(subscribed) = self.playerlists_bus_rx.try_recv() {
+            changed = true;
+            self.update_subscribed_playerlist(subscribed);
+        }
+
+        while let Ok(reply) = self.steam_reply_bus_rx.try_recv() {
+            changed = true;
+            self.steam_reply(reply);
+        }
+
+        changed
+    }
+
+    fn process_logline(&mut self, logline: LogLine) {
+        // log::info!("LobbyThread: Got message: {:?}", logline);
+        if self.follow_log_time {
+            if let Some(when) = logline.when() {
+                self.lobby.log_time = Some(when);
+            }
+        }
+
+        match logline {
+            LogLine::Unknown { line: _ } => {}
+            LogLine::StatusHeader { when } => self.purge_old_players(when),
+            LogLine::ServerInfo { when, info } => self.server_info(when, info),
+            LogLine::StatusForPlayer {
+                when,
+                id,
+                name,
+                steam_id32,
+                connected,
+                ping,
+                loss,
+                state,
+            } => {
+                let steamid = SteamID::from_steam_id32(&steam_id32);
+                self.player_seen(when, id, name, steam_id32);
+                self.player_connection(steamid, connected, ping, loss, state);
+            }
+            LogLine::Kill {
+                when,
+                killer,
+                victim,
+                weapon,
+                crit,
+            } => self.kill(when, killer, victim, weapon, crit),
+            LogLine::AmbiguousKill { when, splits, crit } => {
+                self.ambiguous_kill(when, splits, crit)
+            }
+            LogLine::Connected { when, name } => {
+                self.pending_joins.insert(name, when);
+            }
+            LogLine::Disconnected { when, name, reason } => self.disconnected(when, name, reason),
+            LogLine::NameChange {
+                when,
+                old_name,
+                new_name,
+            } => self.name_change(when, old_name, new_name),
+            LogLine::Suicide { when, name } => self.suicide(when, name),
+            LogLine::Capture {
+                when: _,
+                cappers,
+                point,
+                team,
+            } => self.capture(cappers, point, team),
+            LogLine::Defended {
+                when: _,
+                name,
+                point,
+                team,
+            } => self.defended(name, point, team),
+            LogLine::VoteStarted {
+                when,
+                caller,
+                target,
+            } => self.vote_started(when, caller, target),
+            LogLine::VoteCast { when: _, name, yes } => self.vote_cast(name, yes),
+            LogLine::VoteEnded {
+                when: _,
+                passed,
+                reason,
+            } => self.vote_ended(passed, reason),
+            LogLine::VoteCooldown { when, seconds } => {
+                let seconds = seconds.map_or(DEFAULT_VOTE_COOLDOWN_SECS, i64::from);
+                self.lobby.vote_cooldown_until = Some(when + chrono::Duration::seconds(seconds));
+            }
+            LogLine::ItemFound { when, name, item } => self.item_found(when, name, item),
+            LogLine::Achievement {
+                when,
+                name,
+                achievement,
+            } => self.achievement(when, name, achievement),
+            LogLine::LobbyCreated { when: _when } => self.new_lobby(),
+            LogLine::LobbyDestroyed { when: _when } => {}
+            LogLine::Chat {
+                when,
+                name,
+                message,
+                dead,
+                team,
+            } => self.chat(when, name, message, dead, team),
+            LogLine::ServerMessage { when, message } => self.lobby.chat.push(LobbyChat {
+                when,
+                kind: ChatKind::Server,
+                message,
+                ..Default::default()
+            }),
+            LogLine::PlayerTeam { steam_id32, team } => self.assign_team(steam_id32, team),
+        }
     }
 
-    fn update_scoreboard(&mut self) {
+    /// Returns false if the lobby report was dropped
+    fn update_scoreboard(&mut self) -> bool {
+        self.enrich_from_player_db();
+        self.lobby.update_friendships();
+        self.lobby.update_impostors();
+
         let mut bus = self.bus.lock().unwrap();
-        bus.send_lobby_report(self.lobby.clone());
+        bus.send_lobby_report(self.lobby.clone())
     }
 
-    fn fetch_steam_info(&mut self) {
-        if !self.steam_api.has_key() {
-            return;
+    /// Copy flags and notes from the player database to the players in the lobby.
+    /// Flags from the subscribed playerlists are added, tagged with the list they came from.
+    fn enrich_from_player_db(&mut self) {
+        for player in self.lobby.players.iter_mut() {
+            match self.player_db.get(player.steamid) {
+                Some(record) => {
+                    player.flags.clone_from(&record.flags);
+                    player.notes.clone_from(&record.notes);
+                }
+                None => {
+                    player.flags.clear();
+                    player.notes.clear();
+                }
+            }
+
+            for (source, playerlist) in self.subscribed_playerlists.iter() {
+                if let Some(record) = playerlist.get(player.steamid) {
+                    player.flags.extend(record.flags.iter().map(|entry| {
+                        let mut entry = entry.clone();
+                        entry.source = Some(source.clone());
+                        entry
+                    }));
+                }
+            }
         }
+    }
+
+    /// A subscribed playerlist was fetched, replace the old copy of it
+    pub fn update_subscribed_playerlist(&mut self, subscribed: SubscribedPlayerlist) {
+        let mut playerlist = PlayerDb::default();
+        let added_by = subscribed
+            .playerlist
+            .title()
+            .unwrap_or(&subscribed.source)
+            .to_string();
+        tf2bd_playerlist::merge_playerlist(&mut playerlist, &subscribed.playerlist, &added_by);
+
+        self.subscribed_playerlists
+            .insert(subscribed.source, playerlist);
+    }
 
-        let steamids: Vec<SteamID> = self
+    /// A flag was toggled in the GUI, write it to the player database
+    fn set_player_flag(&mut self, steamid: SteamID, flag: PlayerFlags, active: bool) {
+        let name = self
             .lobby
-            .players
-            .iter()
-            .filter(|p| p.steam_info.is_none())
-            .map(|p| p.steamid)
-            .collect();
+            .get_player(None, Some(steamid))
+            .map(|p| p.name.clone())
+            .unwrap_or_default();
 
-        if steamids.is_empty() {
+        let added_by = self.self_steamid.to_u64().to_string();
+        if self
+            .player_db
+            .set_flag(steamid, &name, flag, active, &added_by)
+        {
+            self.player_db.save();
+        }
+    }
+
+    fn import_tf2bd(&mut self, filename: String) {
+        match tf2bd_playerlist::import_playerlist(&mut self.player_db, &filename) {
+            Ok(count) => {
+                log::info!("Imported {} players from {}", count, filename);
+                self.player_db.save();
+            }
+            Err(error) => log::error!("Could not import playerlist {}: {}", filename, error),
+        }
+    }
+
+    fn export_tf2bd(&mut self, filename: String) {
+        let author = self.self_steamid.to_u64().to_string();
+        match tf2bd_playerlist::export_playerlist(&self.player_db, &filename, &author) {
+            Ok(count) => log::info!("Exported {} players to {}", count, filename),
+            Err(error) => log::error!("Could not export playerlist {}: {}", filename, error),
+        }
+    }
+
+    /// Ask the Steam thread for the Steam info the players are missing
+    fn request_steam_info(&mut self) {
+        let request = self.steam_request();
+        if request.is_empty() {
             return;
         }
 
-        if let Some(steam_players) = self.steam_api.get_player_summaries(steamids) {
-            for steam_player in steam_players.iter() {
-                if let Some(steamid) = SteamID::from_u64_string(&steam_player.steamid) {
-                    if let Some(lobby_player) = self.lobby.get_player_mut(None, Some(steamid)) {
-                        lobby_player.steam_info = Some(PlayerSteamInfo {
-                            steamid,
-                            name: steam_player.personaname.clone(),
-                            avatar: steam_player.avatar.clone(),
-                            avatarmedium: steam_player.avatarmedium.clone(),
-                            avatarfull: steam_player.avatarfull.clone(),
-                            account_age: steam_player.get_account_age(),
-                        });
-                    }
+        if self.bus.lock().unwrap().send_steam_request(request) {
+            self.steam_refresh.clear();
+        }
+    }
+
+    fn steam_request(&self) -> SteamRequest {
+        let missing = |missing: fn(&PlayerSteamInfo) -> bool| -> Vec<SteamID> {
+            self.lobby
+                .players
+                .iter()
+                .filter(|p| p.steam_info.as_ref().is_some_and(missing))
+                .map(|p| p.steamid)
+                .collect()
+        };
+
+        SteamRequest {
+            refresh: self.steam_refresh.clone(),
+            summaries: self
+                .lobby
+                .players
+                .iter()
+                .filter(|p| p.steam_info.is_none())
+                .map(|p| p.steamid)
+                .collect(),
+            bans: missing(|info| info.bans.is_none()),
+            playtime: missing(|info| info.tf2_playtime.is_none()),
+            friends: missing(|info| info.friends.is_none()),
+        }
+    }
+
+    fn steam_reply(&mut self, reply: SteamReply) {
+        match reply {
+            SteamReply::Summaries(steam_players) => self.steam_summaries(steam_players),
+            SteamReply::Bans(steam_bans) => self.steam_bans(steam_bans),
+            SteamReply::OwnedGames(owned_games) => self.steam_playtime(owned_games),
+            SteamReply::FriendLists(friend_lists) => self.steam_friends(friend_lists),
+        }
+    }
+
+    fn steam_summaries(&mut self, steam_players: Vec<SteamApiPlayer>) {
+        for steam_player in steam_players.iter() {
+            if let Some(steamid) = SteamID::from_u64_string(&steam_player.steamid) {
+                if let Some(lobby_player) = self.lobby.get_player_mut(None, Some(steamid)) {
+                    lobby_player.steam_info = Some(PlayerSteamInfo {
+                        steamid,
+                        name: steam_player.personaname.clone(),
+                        avatar: steam_player.avatar.clone(),
+                        avatarmedium: steam_player.avatarmedium.clone(),
+                        avatarfull: steam_player.avatarfull.clone(),
+                        account_age: steam_player.get_account_age(),
+                        bans: None,
+                        tf2_playtime: None,
+                        friends: None,
+                    });
+                }
+            }
+        }
+    }
+
+    fn steam_bans(&mut self, steam_bans: Vec<SteamApiPlayerBans>) {
+        for steam_ban in steam_bans.iter() {
+            if let Some(steamid) = SteamID::from_u64_string(&steam_ban.steam_id) {
+                if let Some(steam_info) = self.steam_info_mut(steamid) {
+                    steam_info.bans = Some(PlayerBans {
+                        vac_bans: steam_ban.number_of_vac_bans,
+                        game_bans: steam_ban.number_of_game_bans,
+                        community_banned: steam_ban.community_banned,
+                        economy_ban: steam_ban.economy_ban.clone(),
+                        days_since_last_ban: steam_ban.days_since_last_ban,
+                    });
                 }
             }
         }
     }
 
+    fn steam_playtime(&mut self, owned_games: Vec<(SteamID, SteamApiOwnedGames)>) {
+        for (steamid, games) in owned_games {
+            let playtime = match games.get_tf2_playtime() {
+                Some(minutes) => Tf2Playtime::Minutes(minutes),
+                None => Tf2Playtime::Private,
+            };
+
+            if let Some(steam_info) = self.steam_info_mut(steamid) {
+                steam_info.tf2_playtime = Some(playtime);
+            }
+        }
+    }
+
+    fn steam_friends(&mut self, friend_lists: Vec<(SteamID, Option<Vec<SteamApiFriend>>)>) {
+        for (steamid, friends) in friend_lists {
+            let friends = match friends {
+                Some(friends) => FriendList::Public(
+                    friends
+                        .iter()
+                        .filter_map(|f| SteamID::from_u64_string(&f.steamid))
+                        .collect(),
+                ),
+                None => FriendList::Private,
+            };
+
+            if let Some(steam_info) = self.steam_info_mut(steamid) {
+                steam_info.friends = Some(friends);
+            }
+        }
+    }
+
+    fn steam_info_mut(&mut self, steamid: SteamID) -> Option<&mut PlayerSteamInfo> {
+        self.lobby
+            .get_player_mut(None, Some(steamid))
+            .and_then(|p| p.steam_info.as_mut())
+    }
+
+    /// Drop the Steam info for a player, so it's fetched again with the next request
+    fn refresh_steam_info(&mut self, steamid: SteamID) {
+        log::info!("Refreshing Steam info for {}", steamid.to_u64());
+        self.steam_refresh.push(steamid);
+
+        if let Some(player) = self.lobby.get_player_mut(None, Some(steamid)) {
+            player.steam_info = None;
+        }
+    }
+
     fn new_lobby(&mut self) {
         log::info!("Creating new lobby");
         self.lobby = Lobby::new();
     }
 
+    fn server_info(&mut self, when: DateTime<Local>, info: ServerInfoLine) {
+        let server = &mut self.lobby.server;
+        match info {
+            ServerInfoLine::Hostname(hostname) => server.hostname = hostname,
+            ServerInfoLine::Version(version) => server.version = version,
+            ServerInfoLine::UdpIp(address) => server.address = address,
+            ServerInfoLine::SteamId(steamid) => server.steamid = steamid,
+            ServerInfoLine::Map(map) => {
+                let old_map = server.map.clone();
+                if server.set_map(when, &map) && !old_map.is_empty() {
+                    log::info!("Map changed from {} to {}", old_map, map);
+                }
+            }
+            ServerInfoLine::Tags(tags) => server.tags = tags,
+            ServerInfoLine::Players { humans, bots, max } => {
+                server.humans = humans;
+                server.bots = bots;
+                server.max_players = max;
+            }
+        }
+    }
+
     /// Add this player to the list of players if not already added
     fn player_seen(&mut self, when: DateTime<Local>, id: u32, name: String, steam_id32: String) {
         // log::info!("Player seen: {} ({})", name, steam_id32);
         let steamid = SteamID::from_steam_id32(steam_id32.as_str());
 
-        // Update last_seen for existing player
-        for player in self.lobby.players.iter_mut() {
-            if player.steamid == steamid {
+        if self.player_db.update_name(steamid, &name) {
+            self.player_db.save();
+        }
+
+        // Update last_seen for existing player, or add new player if not found in the list
+        match self.lobby.players.iter_mut().find(|p| p.steamid == steamid) {
+            Some(player) => {
                 player.id = id;
-                player.name.clone_from(&name);
+                // Also catches renames we missed in the log
+                player.rename(when, &name);
                 player.last_seen = when;
-                return;
+            }
+            None => {
+                // Players that rejoin keep their stats
+                let mut player = match self.lobby.take_recently_left(steamid) {
+                    Some(mut player) => {
+                        player.id = id;
+                        player.rename(when, &name);
+                        player.last_seen = when;
+                        player
+                    }
+                    None => Player::new(id, steamid, name.clone(), when),
+                };
+
+                if let Some(joined) = self.pending_joins.remove(&name) {
+                    player.joined = joined;
+                }
+
+                self.lobby.players.push(player);
             }
         }
 
-        // Add new player if not found in the list
-        self.lobby.players.push(Player {
-            id,
-            steamid,
-            name: name.clone(),
-            team: Team::Unknown,
-            kills: 0,
-            deaths: 0,
-            crit_kills: 0,
-            crit_deaths: 0,
-            kills_with: Vec::new(),
-            last_seen: when,
-            steam_info: None,
-        });
+        self.apply_name_rules(when, steamid, &name);
+    }
+
+    fn vote_started(&mut self, when: DateTime<Local>, caller: String, target: String) {
+        let target_steamid = self.resolve_name(&target);
+
+        log::info!("{} called a vote to kick {}", caller, target);
+        self.lobby.vote = Some(Vote::new(when, &caller, &target, target_steamid));
+    }
+
+    fn vote_cast(&mut self, name: String, yes: bool) {
+        if let Some(vote) = self
+            .lobby
+            .vote
+            .as_mut()
+            .filter(|vote| vote.result.is_none())
+        {
+            vote.cast(&name, yes);
+        }
+    }
+
+    fn vote_ended(&mut self, passed: bool, reason: String) {
+        if let Some(vote) = self
+            .lobby
+            .vote
+            .as_mut()
+            .filter(|vote| vote.result.is_none())
+        {
+            log::info!(
+                "Vote to kick {} {}. Yes: {}, no: {}",
+                vote.target,
+                if passed { "passed" } else { "failed" },
+                vote.yes,
+                vote.no
+            );
+
+            vote.result = Some(if passed {
+                VoteResult::Passed
+            } else {
+                VoteResult::Failed(reason)
+            });
+        }
+    }
+
+    fn disconnected(&mut self, when: DateTime<Local>, name: String, reason: String) {
+        self.pending_joins.remove(&name);
+
+        if let Some(steamid) = self.resolve_name(&name) {
+            self.lobby.player_left(steamid, when, &reason);
+        }
+    }
+
+    /// Rename the player right away, so kills and chat are found by the new name
+    fn name_change(&mut self, when: DateTime<Local>, old_name: String, new_name: String) {
+        let Some(player) = self.resolved_player_mut(&old_name) else {
+            log::debug!("Name change: player '{}' not found", old_name);
+            return;
+        };
+
+        player.rename(when, &new_name);
+        let steamid = player.steamid;
+
+        if self.player_db.update_name(steamid, &new_name) {
+            self.player_db.save();
+        }
+
+        self.apply_name_rules(when, steamid, &new_name);
+    }
+
+    fn apply_name_rules(&mut self, when: DateTime<Local>, steamid: SteamID, name: &str) {
+        let rules: Vec<(String, RuleActions)> = self
+            .rules
+            .match_name(name)
+            .iter()
+            .map(|rule| (rule.description.clone(), rule.actions.clone()))
+            .collect();
+        for (rule, actions) in rules {
+            self.apply_rule(when, steamid, rule, &actions, name);
+        }
+    }
+
+    /// Keep the connection info from the status output
+    fn player_connection(
+        &mut self,
+        steamid: SteamID,
+        connected: chrono::Duration,
+        ping: u32,
+        loss: u32,
+        state: String,
+    ) {
+        if let Some(player) = self.lobby.get_player_mut(None, Some(steamid)) {
+            player.connected = connected;
+            player.ping = Some(ping);
+            player.loss = loss;
+            player.state = match state.as_str() {
+                "active" => PlayerState::Active,
+                _ => PlayerState::Spawning,
+            };
+        }
     }
 
     fn assign_team(&mut self, steam_id32: String, team: String) {
@@ -181,19 +677,9 @@ impl LobbyThread {
         }
 
         // Add new player if not found in the list
-        self.lobby.players.push(Player {
-            id: 0,
-            steamid,
-            name: steam_id32.clone(),
-            team: Team::Unknown,
-            kills: 0,
-            deaths: 0,
-            crit_kills: 0,
-            crit_deaths: 0,
-            kills_with: Vec::new(),
-            last_seen: Local::now(),
-            steam_info: None,
-        });
+        self.lobby
+            .players
+            .push(Player::new(0, steamid, steam_id32, self.lobby.now()));
     }
 
     fn kill(
@@ -204,7 +690,10 @@ impl LobbyThread {
         weapon: String,
         crit: bool,
     ) {
-        if let Some(player) = self.lobby.get_player_mut(Some(killer.as_str()), None) {
+        let (killer_steamid, victim_steamid) = self.resolve_kill(&killer, &victim);
+
+        if let Some(player) = killer_steamid.and_then(|s| self.lobby.get_player_mut(None, Some(s)))
+        {
             player.kills += 1;
             if crit {
                 player.crit_kills += 1;
@@ -214,10 +703,11 @@ impl LobbyThread {
                 crit,
             });
         } else {
-            log::warn!("Killer not found: '{}'", victim);
+            log::warn!("Killer not found: '{}'", killer);
         }
 
-        if let Some(player) = self.lobby.get_player_mut(Some(victim.as_str()), None) {
+        if let Some(player) = victim_steamid.and_then(|s| self.lobby.get_player_mut(None, Some(s)))
+        {
             player.deaths += 1;
             if crit {
                 player.crit_deaths += 1;
@@ -227,11 +717,142 @@ impl LobbyThread {
         }
     }
 
+    /// The killer and victim of a kill line. When a name fits more than one player,
+    /// like an impostor and the player whose name was copied, the teams decide as
+    /// players can't kill their own team. A name that is still ambiguous is not counted.
+    fn resolve_kill(&self, killer: &str, victim: &str) -> (Option<SteamID>, Option<SteamID>) {
+        let killers = self.lobby.find_by_name(killer);
+        let victims = self.lobby.find_by_name(victim);
+        let only = |candidates: &[SteamID]| match candidates {
+            [steamid] => Some(*steamid),
+            _ => None,
+        };
+
+        if killers.len() <= 1 && victims.len() <= 1 {
+            return (only(&killers), only(&victims));
+        }
+
+        let team = |steamid: SteamID| {
+            self.lobby
+                .get_player(None, Some(steamid))
+                .map_or(Team::Unknown, |p| p.team)
+        };
+        let pairs: Vec<(SteamID, SteamID)> = killers
+            .iter()
+            .flat_map(|k| victims.iter().map(move |v| (*k, *v)))
+            .filter(|(k, v)| {
+                let same_team = team(*k) == team(*v) && team(*k) != Team::Unknown;
+                k != v && !same_team
+            })
+            .collect();
+
+        match pairs.as_slice() {
+            [(k, v)] => (Some(*k), Some(*v)),
+            _ => (only(&killers), only(&victims)),
+        }
+    }
+
+    /// The parser's roster couldn't settle it, try with the players in the lobby
+    fn ambiguous_kill(&mut self, when: DateTime<Local>, splits: Vec<KillSplit>, crit: bool) {
+        let mut known: Vec<KillSplit> = splits
+            .iter()
+            .filter(|split| {
+                !self.lobby.find_by_name(&split.killer).is_empty()
+                    && !self.lobby.find_by_name(&split.victim).is_empty()
+            })
+            .cloned()
+            .collect();
+
+        if known.len() == 1 {
+            let split = known.remove(0);
+            self.kill(when, split.killer, split.victim, split.weapon, crit);
+        } else {
+            log::warn!("Ambiguous kill line, not counted: {:?}", splits);
+        }
+    }
+
+    /// The one player a name from the log belongs to, found like the kills and chat.
+    /// A name that fits more than one player, like an impostor's, is not counted.
+    fn resolve_name(&self, name: &str) -> Option<SteamID> {
+        match self.lobby.find_by_name(name)[..] {
+            [steamid] => Some(steamid),
+            _ => None,
+        }
+    }
+
+    fn resolved_player_mut(&mut self, name: &str) -> Option<&mut Player> {
+        let steamid = self.resolve_name(name)?;
+        self.lobby.get_player_mut(None, Some(steamid))
+    }
+
+    fn capture(&mut self, cappers: String, point: String, team: u32) {
+        let names = self.lobby.split_names(&cappers);
+        log::info!("{} captured by team #{}: {:?}", point, team, names);
+
+        // The cappers are on the same team, the ones that are certain tell the others apart
+        let team_of = |steamid| {
+            self.lobby
+                .get_player(None, Some(steamid))
+                .map_or(Team::Unknown, |p| p.team)
+        };
+        let mut candidates: Vec<Vec<SteamID>> = names
+            .iter()
+            .map(|name| self.lobby.find_by_name(name))
+            .collect();
+        let teams: Vec<Team> = candidates
+            .iter()
+            .filter_map(|c| match c[..] {
+                [steamid] => Some(team_of(steamid)),
+                _ => None,
+            })
+            .filter(|team| *team != Team::Unknown)
+            .collect();
+        for c in candidates.iter_mut().filter(|c| c.len() > 1) {
+            c.retain(|steamid| teams.contains(&team_of(*steamid)));
+        }
+
+        for (name, c) in names.iter().zip(candidates) {
+            if let [steamid] = c[..] {
+                if let Some(player) = self.lobby.get_player_mut(None, Some(steamid)) {
+                    player.captures += 1;
+                }
+            } else {
+                log::debug!("Capture: player '{}' not found or ambiguous", name);
+            }
+        }
+    }
+
+    fn defended(&mut self, name: String, point: String, team: u32) {
+        log::info!("{} defended by {} for team #{}", point, name, team);
+
+        if let Some(player) = self.resolved_player_mut(&name) {
+            player.defenses += 1;
+        }
+    }
+
+    fn item_found(&mut self, when: DateTime<Local>, name: String, item: String) {
+        if let Some(player) = self.resolved_player_mut(&name) {
+            player.found_items.push(FoundItem { item, when });
+        } else {
+            log::warn!("Player not found or ambiguous: '{}'", name);
+        }
+    }
+
+    fn achievement(&mut self, when: DateTime<Local>, name: String, achievement: String) {
+        if let Some(player) = self.resolved_player_mut(&name) {
+            player
+                .achievements
+                .push(EarnedAchievement { achievement, when });
+        } else {
+            log::warn!("Player not found or ambiguous: '{}'", name);
+        }
+    }
+
     fn suicide(&mut self, _when: DateTime<Local>, name: String) {
-        if let Some(player) = self.lobby.get_player_mut(Some(name.as_str()), None) {
+        if let Some(player) = self.resolved_player_mut(&name) {
             player.deaths += 1;
         } else {
-            log::warn!("Player not found: '{}'", name);
+            log::warn!("Player not found or ambiguous: '{}'", name);
         }
     }
 
@@ -243,31 +864,662 @@ impl LobbyThread {
         dead: bool,
         team: bool,
     ) {
-        if let Some(player) = self.lobby.get_player(Some(name.as_str()), None) {
+        let mut candidates = self.lobby.find_by_name(&name);
+
+        // Team chat only comes from our own team, which tells same looking names apart
+        if candidates.len() > 1 && team {
+            let team_of = |steamid| self.lobby.get_player(None, Some(steamid)).map(|p| p.team);
+            let own_team = team_of(self.self_steamid);
+            candidates.retain(|steamid| team_of(*steamid) == own_team);
+        }
+
+        if let [steamid] = candidates[..] {
+            let rules: Vec<(String, RuleActions)> = self
+                .rules
+                .match_chat(&message)
+                .iter()
+                .map(|rule| (rule.description.clone(), rule.actions.clone()))
+                .collect();
+            for (rule, actions) in rules {
+                self.apply_rule(when, steamid, rule, &actions, &message);
+            }
+
             self.lobby.chat.push(LobbyChat {
                 when,
-                steamid: player.steamid,
+                kind: ChatKind::Player,
+                steamid,
+                name,
                 message,
                 dead,
                 team,
             })
         } else {
-            log::warn!("Player not found: '{}'", name);
+            // Kept so spoofed chat can be seen, but rules don't apply. A name like
+            // "Console" is no proof of anything, the server's own messages are ServerMessage lines.
+            log::debug!("Chat from unknown or ambiguous player: '{}'", name);
+            self.lobby.chat.push(LobbyChat {
+                when,
+                kind: ChatKind::Unresolved,
+                steamid: SteamID::default(),
+                name,
+                message,
+                dead,
+                team,
+            })
+        }
+    }
+
+    /// A rule from rules.json matched the player's name or chat message.
+    /// The match is recorded on the player, and each rule only fires once per player and lobby.
+    fn apply_rule(
+        &mut self,
+        when: DateTime<Local>,
+        steamid: SteamID,
+        rule: String,
+        actions: &RuleActions,
+        text: &str,
+    ) {
+        let Some(player) = self.lobby.get_player_mut(None, Some(steamid)) else {
+            return;
+        };
+
+        if player.rule_matches.iter().any(|m| m.rule == rule) {
+            return;
+        }
+
+        log::info!(
+            "Rule '{}' matched player '{}': '{}'",
+            rule,
+            player.name,
+            text
+        );
+        player.rule_matches.push(RuleMatch {
+            rule: rule.clone(),
+            when,
+            text: text.to_string(),
+        });
+
+        let id = player.id;
+        let name = player.name.clone();
+
+        let added_by = format!("Rule '{}'", rule);
+        let mut changed = false;
+        for flag in actions.mark.iter() {
+            changed |= self
+                .player_db
+                .set_flag(steamid, &name, *flag, true, &added_by);
+        }
+        if changed {
+            self.player_db.save();
+        }
+
+        // A vote called now would just fail
+        let can_vote =
+            !self.lobby.vote_in_progress(when) && self.lobby.vote_cooldown_secs(when).is_none();
+
+        let mut bus = self.bus.lock().unwrap();
+        if actions.votekick && id != 0 && can_vote {
+            bus.send_rcon_cmd(&format!("callvote kick \"{} cheating\"", id));
+        }
+        if let Some(announce) = &actions.announce {
+            // Quotes and semicolons in the name could break out of the say command
+            let name: String = name.chars().filter(|c| *c != '"' && *c != ';').collect();
+            let message = announce.replace("{name}", &name);
+            bus.send_rcon_cmd(&format!("say \"{}\"", message));
         }
     }
 
-    /// Players who has a last_seen older than 30 seconds are removed from the lobby
+    /// Players not seen in status for a while have left without a disconnect line
     fn purge_old_players(&mut self, when: DateTime<Local>) {
-        let mut new_vec: Vec<Player> = vec![];
+        let gone: Vec<SteamID> = self
+            .lobby
+            .players
+            .iter()
+            .filter(|player| (when - player.last_seen).num_seconds() >= PLAYER_TIMEOUT_SECONDS)
+            .map(|player| player.steamid)
+            .collect();
 
-        for player in self.lobby.players.iter_mut() {
-            let age_seconds = (when - player.last_seen).num_seconds();
-            if age_seconds < 30 {
-                // Player is still active, keep it
-                new_vec.push(player.clone());
-            }
+        for steamid in gone {
+            self.lobby.player_left(steamid, when, "Not in status");
         }
 
-        self.lobby.players = new_vec;
+        // Connected lines for players that never showed up
+        self.pending_joins
+            .retain(|_, joined| (when - *joined).num_seconds() < 5 * 60);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tf2::{
+        lobby::FriendList,
+        steam::{fake_steam_api::FakeSteamApi, steam_thread::SteamThread},
+    };
+    use chrono::Duration;
+
+    const NEW_ACCOUNT: u64 = 76561199179248685;
+    const OLD_ACCOUNT: u64 = 76561197960435530;
+    const UNKNOWN_ACCOUNT: u64 = 76561197994359533;
+
+    fn test_settings() -> AppSettings {
+        serde_json::from_value(serde_json::json!({
+            "log_filename": "console.log",
+            "exe_filename": "tf_win64.exe",
+            "self_steamid64": OLD_ACCOUNT,
+            "steam_api_key": "",
+            "rcon_password": "rconpwd",
+            "rcon_ip": "127.0.0.1",
+            "rcon_port": 40434
+        }))
+        .unwrap()
+    }
+
+    fn test_lobby_thread() -> LobbyThread {
+        let bus = Arc::new(Mutex::new(AppBus::new()));
+        let mut lobby_thread = LobbyThread::new(
+            &test_settings(),
+            &bus,
+            PlayerDb::default(),
+            RuleEngine::default(),
+        );
+
+        let now = Local::now();
+        for (id, steamid) in [NEW_ACCOUNT, OLD_ACCOUNT, UNKNOWN_ACCOUNT]
+            .iter()
+            .enumerate()
+        {
+            let steam_id32 = SteamID::from_u64(*steamid).to_steam_id32();
+            lobby_thread.player_seen(now, id as u32, format!("Player{}", id), steam_id32);
+        }
+
+        lobby_thread
+    }
+
+    /// A Steam thread on the same bus as the lobby thread
+    fn steam_thread_with(lobby_thread: &LobbyThread, steam_api: FakeSteamApi) -> SteamThread {
+        SteamThread::new(&lobby_thread.bus, Box::new(steam_api))
+    }
+
+    /// One round trip to the Steam thread
+    fn fetch_steam(lobby_thread: &mut LobbyThread, steam_thread: &mut SteamThread) {
+        steam_thread.fetch(lobby_thread.steam_request());
+        lobby_thread.steam_refresh.clear();
+        lobby_thread.process_bus();
+    }
+
+    fn steam_info(lobby_thread: &LobbyThread, steamid: u64) -> Option<&PlayerSteamInfo> {
+        lobby_thread
+            .lobby
+            .get_player(None, Some(SteamID::from_u64(steamid)))
+            .unwrap()
+            .steam_info
+            .as_ref()
+    }
+
+    #[test]
+    fn test_enrich_from_fake_steam_api() {
+        let new_account = SteamID::from_u64(NEW_ACCOUNT);
+        let old_account = SteamID::from_u64(OLD_ACCOUNT);
+
+        let mut steam_api = FakeSteamApi::default();
+        steam_api.add_player(new_account, "New", Local::now() - Duration::days(30));
+        steam_api.add_player(old_account, "Old", Local::now() - Duration::days(5 * 365));
+        steam_api.add_bans(old_account, 1, 0);
+        steam_api.tf2_minutes.insert(old_account, 2000 * 60);
+        steam_api
+            .friend_lists
+            .insert(old_account, vec![new_account]);
+
+        let mut lobby_thread = test_lobby_thread();
+        let mut steam_thread = steam_thread_with(&lobby_thread, steam_api);
+        fetch_steam(&mut lobby_thread, &mut steam_thread);
+        lobby_thread.update_scoreboard();
+
+        // The account age drives the green new account marker in the scoreboard
+        let info = steam_info(&lobby_thread, NEW_ACCOUNT).unwrap();
+        assert!(info.is_account_new());
+        assert!(!info.bans.as_ref().unwrap().has_bans());
+        assert_eq!(info.tf2_playtime, Some(Tf2Playtime::Private));
+        assert_eq!(info.friends, Some(FriendList::Private));
+
+        let info = steam_info(&lobby_thread, OLD_ACCOUNT).unwrap();
+        assert!(!info.is_account_new());
+        assert!(info.bans.as_ref().unwrap().has_bans());
+        assert_eq!(info.tf2_playtime.unwrap().hours(), Some(2000));
+
+        // Friends through the public list of the old account
+        let party = |steamid| {
+            lobby_thread
+                .lobby
+                .get_player(None, Some(SteamID::from_u64(steamid)))
+                .unwrap()
+                .party
+        };
+        assert_eq!(party(NEW_ACCOUNT), Some(1));
+        assert_eq!(party(OLD_ACCOUNT), Some(1));
+
+        // Steam doesn't know this player, so there's nothing to show
+        assert!(steam_info(&lobby_thread, UNKNOWN_ACCOUNT).is_none());
+        assert_eq!(party(UNKNOWN_ACCOUNT), None);
+    }
+
+    #[test]
+    fn test_name_change_keeps_stats() {
+        let mut lobby_thread = test_lobby_thread();
+        let when = Local::now();
+
+        lobby_thread.name_change(when, "Player0".to_string(), "Renamed".to_string());
+        lobby_thread.kill(
+            when,
+            "Renamed".to_string(),
+            "Player1".to_string(),
+            "scattergun".to_string(),
+            false,
+        );
+
+        let player = lobby_thread
+            .lobby
+            .get_player(None, Some(SteamID::from_u64(NEW_ACCOUNT)))
+            .unwrap();
+        assert_eq!(player.name, "Renamed");
+        assert_eq!(player.kills, 1);
+        assert_eq!(player.renames, 1);
+        assert_eq!(player.previous_names[0].name, "Player0");
+
+        // Status with the new name is not another rename
+        let steam_id32 = SteamID::from_u64(NEW_ACCOUNT).to_steam_id32();
+        lobby_thread.player_seen(when, 0, "Renamed".to_string(), steam_id32);
+        assert_eq!(lobby_thread.lobby.players[0].renames, 1);
+    }
+
+    #[test]
+    fn test_process_bus() {
+        let mut lobby_thread = test_lobby_thread();
+        assert!(!lobby_thread.process_bus());
+
+        let when = Local::now();
+        AppBus::send_logline(
+            &lobby_thread.bus,
+            LogLine::Connected {
+                when,
+                name: "Player3".to_string(),
+            },
+        );
+
+        assert!(lobby_thread.process_bus());
+        assert_eq!(lobby_thread.pending_joins.get("Player3"), Some(&when));
+        assert!(!lobby_thread.process_bus());
+    }
+
+    #[test]
+    fn test_item_found_and_achievement() {
+        let mut lobby_thread = test_lobby_thread();
+        let when = Local::now();
+
+        lobby_thread.process_logline(LogLine::ItemFound {
+            when,
+            name: "Player1".to_string(),
+            item: "Strange Scattergun".to_string(),
+        });
+        lobby_thread.process_logline(LogLine::Achievement {
+            when,
+            name: "Player1".to_string(),
+            achievement: "Krazy Ivan".to_string(),
+        });
+        lobby_thread.process_logline(LogLine::ItemFound {
+            when,
+            name: "Not here".to_string(),
+            item: "Refined Metal".to_string(),
+        });
+
+        let player = lobby_thread
+            .lobby
+            .get_player(Some("Player1"), None)
+            .unwrap();
+        assert_eq!(
+            player.found_items,
+            vec![FoundItem {
+                item: "Strange Scattergun".to_string(),
+                when
+            }]
+        );
+        assert_eq!(player.achievements[0].achievement, "Krazy Ivan");
+
+        let player = lobby_thread
+            .lobby
+            .get_player(Some("Player0"), None)
+            .unwrap();
+        assert!(player.found_items.is_empty());
+    }
+
+    #[test]
+    fn test_chat_kinds() {
+        let mut lobby_thread = test_lobby_thread();
+        let when = Local::now();
+
+        let chat = |name: &str| LogLine::Chat {
+            when,
+            name: name.to_string(),
+            message: "hello".to_string(),
+            dead: false,
+            team: false,
+        };
+        lobby_thread.process_logline(chat("Player1"));
+        lobby_thread.process_logline(chat("Console"));
+        lobby_thread.process_logline(chat("Not here"));
+        lobby_thread.process_logline(LogLine::ServerMessage {
+            when,
+            message: "[SM] Next map: pl_upward".to_string(),
+        });
+
+        let kinds: Vec<(ChatKind, &str)> = lobby_thread
+            .lobby
+            .chat
+            .iter()
+            .map(|chat| (chat.kind, chat.name.as_str()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (ChatKind::Player, "Player1"),
+                // Anyone can be named Console
+                (ChatKind::Unresolved, "Console"),
+                (ChatKind::Unresolved, "Not here"),
+                (ChatKind::Server, ""),
+            ]
+        );
+        assert_eq!(
+            lobby_thread.lobby.chat[0].steamid,
+            SteamID::from_u64(OLD_ACCOUNT)
+        );
+    }
+
+    #[test]
+    fn test_impostor_kills_and_chat() {
+        let mut lobby_thread = test_lobby_thread();
+        let when = Local::now();
+
+        // Player0 copies the name of Player1, but the log shows both the same
+        lobby_thread.lobby.players[0].name = "Player1\u{200B}".to_string();
+        lobby_thread.lobby.players[0].team = Team::Invaders;
+        lobby_thread.lobby.players[1].team = Team::Defendes;
+        lobby_thread.lobby.players[2].team = Team::Invaders;
+        lobby_thread.update_scoreboard();
+        assert!(lobby_thread.lobby.players[0].suspected_impostor);
+        assert!(!lobby_thread.lobby.players[1].suspected_impostor);
+
+        // Only Player1 is on the other team than the victim
+        lobby_thread.process_logline(LogLine::Kill {
+            when,
+            killer: "Player1 ".to_string(),
+            victim: "Player2".to_string(),
+            weapon: "scattergun".to_string(),
+            crit: false,
+        });
+        assert_eq!(lobby_thread.lobby.players[0].kills, 0);
+        assert_eq!(lobby_thread.lobby.players[1].kills, 1);
+
+        // Exact names are used when they match
+        lobby_thread.process_logline(LogLine::Kill {
+            when,
+            killer: "Player1\u{200B}".to_string(),
+            victim: "Player1".to_string(),
+            weapon: "scattergun".to_string(),
+            crit: false,
+        });
+        assert_eq!(lobby_thread.lobby.players[0].kills, 1);
+        assert_eq!(lobby_thread.lobby.players[1].deaths, 1);
+
+        // Team chat is from our own team. We are Player1.
+        lobby_thread.process_logline(LogLine::Chat {
+            when,
+            name: "Player1 ".to_string(),
+            message: "hi".to_string(),
+            dead: false,
+            team: true,
+        });
+        let chat = lobby_thread.lobby.chat.last().unwrap();
+        assert_eq!(chat.kind, ChatKind::Player);
+        assert_eq!(chat.steamid, SteamID::from_u64(OLD_ACCOUNT));
+
+        // All chat can't tell them apart
+        lobby_thread.process_logline(LogLine::Chat {
+            when,
+            name: "Player1 ".to_string(),
+            message: "hi".to_string(),
+            dead: false,
+            team: false,
+        });
+        assert_eq!(
+            lobby_thread.lobby.chat.last().unwrap().kind,
+            ChatKind::Unresolved
+        );
+    }
+
+    #[test]
+    fn test_impostor_objectives_and_suicide() {
+        let mut lobby_thread = test_lobby_thread();
+        let when = Local::now();
+
+        // Player0 copies the name of Player1 and plays with Player2
+        lobby_thread.lobby.players[0].name = "Player1\u{200B}".to_string();
+        lobby_thread.lobby.players[0].team = Team::Invaders;
+        lobby_thread.lobby.players[1].team = Team::Defendes;
+        lobby_thread.lobby.players[2].team = Team::Invaders;
+
+        // Capping with Player2 puts the impostor on the Invaders
+        lobby_thread.process_logline(LogLine::Capture {
+            when,
+            cappers: "Player1 , Player2".to_string(),
+            point: "the cart".to_string(),
+            team: 3,
+        });
+        assert_eq!(lobby_thread.lobby.players[0].captures, 1);
+        assert_eq!(lobby_thread.lobby.players[1].captures, 0);
+        assert_eq!(lobby_thread.lobby.players[2].captures, 1);
+
+        // The exact name is the impostor's
+        lobby_thread.process_logline(LogLine::Suicide {
+            when,
+            name: "Player1\u{200B}".to_string(),
+        });
+        let deaths = |lobby_thread: &LobbyThread| {
+            let steamid = SteamID::from_u64(OLD_ACCOUNT);
+            let player = lobby_thread.lobby.get_player(None, Some(steamid)).unwrap();
+            (lobby_thread.lobby.players[0].deaths, player.deaths)
+        };
+        assert_eq!(deaths(&lobby_thread), (1, 0));
+
+        // A suicide can't tell them apart
+        lobby_thread.process_logline(LogLine::Suicide {
+            when,
+            name: "Player1 ".to_string(),
+        });
+        assert_eq!(deaths(&lobby_thread), (1, 0));
+
+        lobby_thread.process_logline(LogLine::Suicide {
+            when,
+            name: "Player1".to_string(),
+        });
+        assert_eq!(deaths(&lobby_thread), (1, 1));
+    }
+
+    #[test]
+    fn test_ping_from_status() {
+        let mut lobby_thread = test_lobby_thread();
+        let steamid = SteamID::from_u64(OLD_ACCOUNT);
+        assert_eq!(lobby_thread.lobby.players[1].ping, None);
+
+        lobby_thread.process_logline(LogLine::StatusForPlayer {
+            when: Local::now(),
+            id: 1,
+            name: "Player1".to_string(),
+            steam_id32: steamid.to_steam_id32(),
+            connected: Duration::minutes(5),
+            ping: 12,
+            loss: 0,
+            state: "active".to_string(),
+        });
+        assert_eq!(lobby_thread.lobby.players[1].ping, Some(12));
+    }
+
+    #[test]
+    fn test_ambiguous_kill() {
+        let mut lobby_thread = test_lobby_thread();
+        let split = |killer: &str, victim: &str| KillSplit {
+            killer: killer.to_string(),
+            victim: victim.to_string(),
+            weapon: "scattergun".to_string(),
+        };
+
+        // "Player0 killed Player1 killed Player2 with scattergun."
+        let splits = vec![
+            split("Player0", "Player1 killed Player2"),
+            split("Player0 killed Player1", "Player2"),
+        ];
+        lobby_thread.ambiguous_kill(Local::now(), splits.clone(), false);
+        assert!(lobby_thread.lobby.players.iter().all(|p| p.kills == 0));
+
+        lobby_thread.lobby.players[1].name = "Player1 killed Player2".to_string();
+        lobby_thread.ambiguous_kill(Local::now(), splits, false);
+        assert_eq!(lobby_thread.lobby.players[0].kills, 1);
+        assert_eq!(lobby_thread.lobby.players[1].deaths, 1);
+    }
+
+    #[test]
+    fn test_vote_lifecycle() {
+        let mut lobby_thread = test_lobby_thread();
+        let when = Local::now();
+
+        // Votes without a start are ignored
+        lobby_thread.vote_cast("Player0".to_string(), true);
+        assert!(lobby_thread.lobby.vote.is_none());
+
+        lobby_thread.vote_started(when, "Player0".to_string(), "Player1".to_string());
+        lobby_thread.vote_cast("Player0".to_string(), true);
+        lobby_thread.vote_cast("Player2".to_string(), true);
+        lobby_thread.vote_cast("Player1".to_string(), false);
+        assert!(lobby_thread.lobby.vote_in_progress(when));
+
+        lobby_thread.vote_ended(false, "Not enough players voted.".to_string());
+        // Casts after the end don't change the tally
+        lobby_thread.vote_cast("Someone".to_string(), true);
+
+        let vote = lobby_thread.lobby.vote.as_ref().unwrap();
+        assert_eq!(vote.target_steamid, Some(SteamID::from_u64(OLD_ACCOUNT)));
+        assert_eq!((vote.yes, vote.no), (2, 1));
+        assert_eq!(
+            vote.result,
+            Some(VoteResult::Failed("Not enough players voted.".to_string()))
+        );
+        assert!(!lobby_thread.lobby.vote_in_progress(when));
+    }
+
+    #[test]
+    fn test_replayed_vote() {
+        let mut lobby_thread = test_lobby_thread();
+        lobby_thread.follow_log_time();
+        let when = Local::now() - Duration::days(10);
+
+        lobby_thread.process_logline(LogLine::VoteStarted {
+            when,
+            caller: "Player0".to_string(),
+            target: "Player1".to_string(),
+        });
+        lobby_thread.process_logline(LogLine::VoteCooldown {
+            when: when + Duration::seconds(5),
+            seconds: Some(60),
+        });
+
+        // A vote in an old log is running as of the log's time
+        let lobby = &lobby_thread.lobby;
+        assert_eq!(lobby.now(), when + Duration::seconds(5));
+        assert!(lobby.vote_in_progress(lobby.now()));
+        assert_eq!(lobby.vote_cooldown_secs(lobby.now()), Some(60));
+
+        // Players first seen in tf_lobby_debug are seen as of the log's time too
+        let steamid = SteamID::from_u64(76561198080281312);
+        lobby_thread.process_logline(LogLine::PlayerTeam {
+            steam_id32: steamid.to_steam_id32(),
+            team: "INVADERS".to_string(),
+        });
+        let player = lobby_thread.lobby.get_player(None, Some(steamid)).unwrap();
+        assert_eq!(player.last_seen, when + Duration::seconds(5));
+
+        // The live log goes by the clock
+        assert!(test_lobby_thread().lobby.log_time.is_none());
+    }
+
+    #[test]
+    fn test_join_and_leave() {
+        let mut lobby_thread = test_lobby_thread();
+        let when = Local::now();
+        let steamid = SteamID::from_u64(NEW_ACCOUNT);
+
+        lobby_thread.kill(
+            when,
+            "Player0".to_string(),
+            "Player1".to_string(),
+            "scattergun".to_string(),
+            false,
+        );
+        lobby_thread.disconnected(
+            when,
+            "Player0".to_string(),
+            "Disconnect by user.".to_string(),
+        );
+        assert!(lobby_thread.lobby.get_player(None, Some(steamid)).is_none());
+
+        let left = &lobby_thread.lobby.recently_left[0];
+        assert_eq!(left.player.kills, 1);
+        assert_eq!(left.reason, "Disconnect by user.");
+
+        // Rejoins under a new name, the join time is from the connected line
+        let joined = when + Duration::seconds(20);
+        lobby_thread
+            .pending_joins
+            .insert("Back".to_string(), joined);
+        lobby_thread.player_seen(
+            joined + Duration::seconds(5),
+            7,
+            "Back".to_string(),
+            steamid.to_steam_id32(),
+        );
+
+        let player = lobby_thread.lobby.get_player(None, Some(steamid)).unwrap();
+        assert_eq!(player.kills, 1);
+        assert_eq!(player.joined, joined);
+        assert!(lobby_thread.lobby.recently_left.is_empty());
+
+        // Players missing from status are moved to the recently left list too
+        lobby_thread.purge_old_players(joined + Duration::seconds(60));
+        assert_eq!(lobby_thread.lobby.players.len(), 0);
+        assert_eq!(lobby_thread.lobby.recently_left.len(), 3);
+    }
+
+    #[test]
+    fn test_refresh_steam_info() {
+        let mut steam_api = FakeSteamApi::default();
+        steam_api.add_player(
+            SteamID::from_u64(NEW_ACCOUNT),
+            "New",
+            Local::now() - Duration::days(30),
+        );
+
+        let mut lobby_thread = test_lobby_thread();
+        let mut steam_thread = steam_thread_with(&lobby_thread, steam_api);
+        fetch_steam(&mut lobby_thread, &mut steam_thread);
+        assert!(steam_info(&lobby_thread, NEW_ACCOUNT).is_some());
+
+        lobby_thread.refresh_steam_info(SteamID::from_u64(NEW_ACCOUNT));
+        assert!(steam_info(&lobby_thread, NEW_ACCOUNT).is_none());
+        assert_eq!(
+            lobby_thread.steam_request().refresh,
+            vec![SteamID::from_u64(NEW_ACCOUNT)]
+        );
+
+        fetch_steam(&mut lobby_thread, &mut steam_thread);
+        assert!(steam_info(&lobby_thread, NEW_ACCOUNT).is_some());
     }
 }
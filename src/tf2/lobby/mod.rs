@@ -1,8 +1,22 @@
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Duration, Local};
+use std::collections::{HashMap, HashSet};
 
-use crate::models::steamid::SteamID;
+use crate::models::{player_db::PlayerFlagEntry, steamid::SteamID, PlayerFlags};
 
 pub mod lobby_thread;
+pub mod names;
+
+use names::normalize_name;
+
+/// How many players are kept in the recently left list
+const MAX_RECENTLY_LEFT: usize = 20;
+
+/// Votes run for about 15 seconds. If we miss the result line,
+/// the vote is over after this long anyway.
+const VOTE_TIMEOUT_SECS: i64 = 30;
+
+/// The default sv_vote_creation_timer, used when the cooldown message has no time in it
+pub const DEFAULT_VOTE_COOLDOWN_SECS: i64 = 150;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Team {
@@ -12,28 +26,185 @@ pub enum Team {
     Spec,
 }
 
+/// Commands sent from the GUI to the lobby thread
+#[derive(Debug, Clone)]
+pub enum LobbyCommand {
+    /// Fetch the Steam info for a player again, ignoring the Steam cache
+    RefreshSteamInfo { steamid: SteamID },
+}
+
+/// The player's state in the status output
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PlayerState {
+    Spawning,
+    Active,
+}
+
 #[derive(Debug, Clone)]
 pub struct PlayerKill {
     pub weapon: String,
     pub crit: bool,
 }
 
+/// A rule from rules.json that fired for a player
+#[derive(Debug, Clone)]
+pub struct RuleMatch {
+    pub rule: String,
+    pub when: DateTime<Local>,
+
+    /// The name or chat message that matched
+    pub text: String,
+}
+
+/// An item the player got while we were watching
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoundItem {
+    pub item: String,
+    pub when: DateTime<Local>,
+}
+
+/// An achievement the player earned while we were watching
+#[derive(Debug, Clone, PartialEq)]
+pub struct EarnedAchievement {
+    pub achievement: String,
+    pub when: DateTime<Local>,
+}
+
+/// A name the player had before renaming
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreviousName {
+    pub name: String,
+
+    /// When the player changed from this name
+    pub until: DateTime<Local>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Player {
     pub id: u32,
     pub steamid: SteamID,
     pub name: String,
+
+    /// Older names first. Name stealing bots rename a lot.
+    pub previous_names: Vec<PreviousName>,
+    pub renames: u32,
     pub team: Team,
     pub kills: u32,
     pub deaths: u32,
     pub crit_kills: u32,
     pub crit_deaths: u32,
+
+    /// Control points captured and defended
+    pub captures: u32,
+    pub defenses: u32,
     pub kills_with: Vec<PlayerKill>,
+
+    /// Item drops and achievements. Fresh bot accounts rarely get these.
+    pub found_items: Vec<FoundItem>,
+    pub achievements: Vec<EarnedAchievement>,
     pub last_seen: DateTime<Local>,
 
+    /// From the connected line in the log, or when first seen in status
+    pub joined: DateTime<Local>,
+
+    /// Connection info from the latest status output
+    pub connected: Duration,
+
+    /// None until the player has been seen in status
+    pub ping: Option<u32>,
+    pub loss: u32,
+    pub state: PlayerState,
+
     pub steam_info: Option<PlayerSteamInfo>,
+
+    /// Flags and notes from the player database
+    pub flags: Vec<PlayerFlagEntry>,
+    pub notes: String,
+
+    pub rule_matches: Vec<RuleMatch>,
+
+    /// Players in the lobby that are friends with each other share the same
+    /// party number. None if the player has no friends in the lobby.
+    pub party: Option<u32>,
+
+    /// Other players in the lobby whose names look the same as this player's
+    pub same_name_as: Vec<SteamID>,
+
+    /// A newer account than another player with the same looking name.
+    /// Name stealing bots copy the names of real players.
+    pub suspected_impostor: bool,
 }
 
+impl Player {
+    pub fn new(id: u32, steamid: SteamID, name: String, last_seen: DateTime<Local>) -> Self {
+        Self {
+            id,
+            steamid,
+            name,
+            previous_names: Vec::new(),
+            renames: 0,
+            team: Team::Unknown,
+            kills: 0,
+            deaths: 0,
+            crit_kills: 0,
+            crit_deaths: 0,
+            captures: 0,
+            defenses: 0,
+            kills_with: Vec::new(),
+            found_items: Vec::new(),
+            achievements: Vec::new(),
+            last_seen,
+            joined: last_seen,
+            connected: Duration::zero(),
+            ping: None,
+            loss: 0,
+            state: PlayerState::Spawning,
+            steam_info: None,
+            flags: Vec::new(),
+            notes: String::new(),
+            rule_matches: Vec::new(),
+            party: None,
+            same_name_as: Vec::new(),
+            suspected_impostor: false,
+        }
+    }
+
+    pub fn has_flag(&self, flag: PlayerFlags) -> bool {
+        self.flags.iter().any(|f| f.flag == flag)
+    }
+
+    /// Changes the name and remembers the old one. Returns false if the name is the same.
+    pub fn rename(&mut self, when: DateTime<Local>, name: &str) -> bool {
+        if self.name == name {
+            return false;
+        }
+
+        let old_name = std::mem::replace(&mut self.name, name.to_string());
+        self.previous_names.push(PreviousName {
+            name: old_name,
+            until: when,
+        });
+        self.renames += 1;
+        true
+    }
+
+    /// Connected time as "mm:ss", or "h:mm:ss" after an hour
+    pub fn connected_str(&self) -> String {
+        let seconds = self.connected.num_seconds();
+        if seconds >= 3600 {
+            format!(
+                "{}:{:02}:{:02}",
+                seconds / 3600,
+                (seconds / 60) % 60,
+                seconds % 60
+            )
+        } else {
+            format!("{:02}:{:02}", seconds / 60, seconds % 60)
+        }
+    }
+}
+
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct PlayerSteamInfo {
     pub steamid: SteamID,
@@ -42,6 +213,70 @@ pub struct PlayerSteamInfo {
     pub avatarmedium: String,
     pub avatarfull: String,
     pub account_age: Option<DateTime<Local>>,
+
+    /// None until the bans have been fetched
+    pub bans: Option<PlayerBans>,
+
+    /// None until the playtime has been fetched
+    pub tf2_playtime: Option<Tf2Playtime>,
+
+    /// None until the friend list has been fetched
+    pub friends: Option<FriendList>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FriendList {
+    Public(Vec<SteamID>),
+
+    /// The player's friend list is private
+    Private,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Tf2Playtime {
+    Minutes(u32),
+
+    /// The player's game details are private
+    Private,
+}
+
+impl Tf2Playtime {
+    /// Hours played, None if private
+    pub fn hours(&self) -> Option<u32> {
+        match self {
+            Tf2Playtime::Minutes(minutes) => Some(minutes / 60),
+            Tf2Playtime::Private => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Tf2Playtime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.hours() {
+            Some(hours) => write!(f, "{}", hours),
+            None => write!(f, "private"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerBans {
+    pub vac_bans: u32,
+    pub game_bans: u32,
+    pub community_banned: bool,
+
+    /// "none", "probation" or "banned"
+    pub economy_ban: String,
+    pub days_since_last_ban: u32,
+}
+
+impl PlayerBans {
+    pub fn has_bans(&self) -> bool {
+        self.vac_bans > 0
+            || self.game_bans > 0
+            || self.community_banned
+            || self.economy_ban != "none"
+    }
 }
 
 impl PlayerSteamInfo {
@@ -69,14 +304,158 @@ impl PlayerSteamInfo {
 
 #[derive(Default, Debug, Clone)]
 pub struct Lobby {
+    pub server: ServerInfo,
     pub players: Vec<Player>,
+
+    /// Newest last
+    pub recently_left: Vec<LeftPlayer>,
     pub chat: Vec<LobbyChat>,
+
+    /// Pairs of players in the lobby that are Steam friends
+    pub friendships: Vec<(SteamID, SteamID)>,
+
+    /// The vote in progress, or the last one
+    pub vote: Option<Vote>,
+
+    /// We can't call a vote before this
+    pub vote_cooldown_until: Option<DateTime<Local>>,
+
+    /// The time of the latest log line when replaying a saved log.
+    /// None for the live log, which is as old as the clock.
+    pub log_time: Option<DateTime<Local>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VoteResult {
+    Passed,
+
+    /// With the reason if the server gave one
+    Failed(String),
+}
+
+/// A kick vote, from the vote lines in the log
+#[derive(Debug, Clone)]
+pub struct Vote {
+    pub caller: String,
+    pub target: String,
+    pub target_steamid: Option<SteamID>,
+    pub started: DateTime<Local>,
+
+    /// The tally, as far as the vote lines show it
+    pub yes: u32,
+    pub no: u32,
+
+    /// None while the vote is running
+    pub result: Option<VoteResult>,
+
+    /// Names of the players that have voted, so each vote is counted once
+    voters: Vec<String>,
+}
+
+impl Vote {
+    pub fn new(
+        started: DateTime<Local>,
+        caller: &str,
+        target: &str,
+        target_steamid: Option<SteamID>,
+    ) -> Self {
+        Self {
+            caller: caller.to_string(),
+            target: target.to_string(),
+            target_steamid,
+            started,
+            yes: 0,
+            no: 0,
+            result: None,
+            voters: Vec::new(),
+        }
+    }
+
+    /// Counts a player's vote. Returns false if the player has voted already.
+    pub fn cast(&mut self, name: &str, yes: bool) -> bool {
+        if self.voters.iter().any(|voter| voter == name) {
+            return false;
+        }
+
+        self.voters.push(name.to_string());
+        if yes {
+            self.yes += 1;
+        } else {
+            self.no += 1;
+        }
+        true
+    }
+
+    pub fn is_active(&self, now: DateTime<Local>) -> bool {
+        self.result.is_none() && (now - self.started).num_seconds() < VOTE_TIMEOUT_SECS
+    }
+}
+
+/// A player that has left the server, with the stats they had when leaving
+#[derive(Debug, Clone)]
+pub struct LeftPlayer {
+    pub player: Player,
+    pub left: DateTime<Local>,
+    pub reason: String,
 }
 
+/// The server we are playing on, from the header of the status output
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct ServerInfo {
+    pub hostname: String,
+    pub version: String,
+    pub address: String,
+    pub steamid: String,
+    pub map: String,
+    pub tags: Vec<String>,
+    pub humans: u32,
+    pub bots: u32,
+    pub max_players: u32,
+
+    /// When we first saw the current map. None until the map is known.
+    pub map_started: Option<DateTime<Local>>,
+}
+
+impl ServerInfo {
+    /// Sets the map. Returns true if it's a different map than before.
+    pub fn set_map(&mut self, when: DateTime<Local>, map: &str) -> bool {
+        if self.map == map {
+            return false;
+        }
+
+        self.map = map.to_string();
+        self.map_started = Some(when);
+        true
+    }
+}
+
+/// Who a chat message is from
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChatKind {
+    /// A player in the server
+    #[default]
+    Player,
+
+    /// A message from a server plugin like SourceMod, see LogLine::ServerMessage.
+    /// Chat from a name like "Console" is Unresolved, anyone can take that name.
+    Server,
+
+    /// A name that is not a player in the server, or that fits more than one.
+    /// Could be spoofed chat, or a player that left before we saw them in status.
+    Unresolved,
+}
+
+#[allow(dead_code)]
 #[derive(Default, Debug, Clone)]
 pub struct LobbyChat {
     pub when: DateTime<Local>,
+    pub kind: ChatKind,
+
+    /// Only set for chat from players
     pub steamid: SteamID,
+
+    /// The name as written in the log. Empty for server messages that have no name.
+    pub name: String,
     pub message: String,
     pub dead: bool,
     pub team: bool,
@@ -85,11 +464,32 @@ pub struct LobbyChat {
 impl Lobby {
     pub fn new() -> Self {
         Self {
+            server: ServerInfo::default(),
             players: Vec::new(),
+            recently_left: Vec::new(),
             chat: Vec::new(),
+            friendships: Vec::new(),
+            vote: None,
+            vote_cooldown_until: None,
+            log_time: None,
         }
     }
 
+    /// The time to compare the votes with. The log's time when replaying a saved log.
+    pub fn now(&self) -> DateTime<Local> {
+        self.log_time.unwrap_or_else(Local::now)
+    }
+
+    pub fn vote_in_progress(&self, now: DateTime<Local>) -> bool {
+        self.vote.as_ref().is_some_and(|vote| vote.is_active(now))
+    }
+
+    /// Seconds left until we can call a vote, None if we can call one now
+    pub fn vote_cooldown_secs(&self, now: DateTime<Local>) -> Option<i64> {
+        let secs = (self.vote_cooldown_until? - now).num_seconds();
+        (secs > 0).then_some(secs)
+    }
+
     pub fn get_player(&self, name: Option<&str>, steamid: Option<SteamID>) -> Option<&Player> {
         self.players
             .iter()
@@ -105,4 +505,512 @@ impl Lobby {
             .iter_mut()
             .find(|player| Some(player.name.as_str()) == name || Some(player.steamid) == steamid)
     }
+
+    /// Moves a player to the recently left list
+    pub fn player_left(&mut self, steamid: SteamID, when: DateTime<Local>, reason: &str) {
+        let Some(index) = self.players.iter().position(|p| p.steamid == steamid) else {
+            return;
+        };
+
+        let player = self.players.remove(index);
+        self.recently_left.push(LeftPlayer {
+            player,
+            left: when,
+            reason: reason.to_string(),
+        });
+
+        if self.recently_left.len() > MAX_RECENTLY_LEFT {
+            self.recently_left.remove(0);
+        }
+    }
+
+    /// Takes a player back from the recently left list, when they rejoin
+    pub fn take_recently_left(&mut self, steamid: SteamID) -> Option<Player> {
+        let index = self
+            .recently_left
+            .iter()
+            .position(|left| left.player.steamid == steamid)?;
+
+        Some(self.recently_left.remove(index).player)
+    }
+
+    /// Splits a ", " separated list of player names, like the cappers of a control point.
+    /// Names can contain ", " too, so the names of the players in the lobby are tried first.
+    /// Parts that don't match any player are split on ", ".
+    pub fn split_names(&self, list: &str) -> Vec<String> {
+        let mut names: Vec<&str> = self
+            .players
+            .iter()
+            .map(|p| p.name.as_str())
+            .filter(|name| !name.is_empty())
+            .collect();
+
+        // Longer names first, so a player named "A, B" wins over a player named "A"
+        names.sort_by_key(|name| std::cmp::Reverse(name.len()));
+
+        split_with_roster(list, &names)
+    }
+
+    /// Finds the friendships between the players in the lobby, and groups
+    /// friends of friends into parties. A friendship is found if either of the
+    /// two players has a public friend list, so private lists are covered as
+    /// long as the friend's list is public.
+    pub fn update_friendships(&mut self) {
+        let in_lobby: HashSet<SteamID> = self.players.iter().map(|p| p.steamid).collect();
+
+        let mut friendships = HashSet::new();
+        for player in &self.players {
+            let friends = match player.steam_info.as_ref().and_then(|i| i.friends.as_ref()) {
+                Some(FriendList::Public(friends)) => friends,
+                _ => continue,
+            };
+
+            for friend in friends.iter().filter(|f| in_lobby.contains(f)) {
+                let pair = if player.steamid.to_u64() < friend.to_u64() {
+                    (player.steamid, *friend)
+                } else {
+                    (*friend, player.steamid)
+                };
+                friendships.insert(pair);
+            }
+        }
+
+        let mut friendships: Vec<(SteamID, SteamID)> = friendships.into_iter().collect();
+        friendships.sort_by_key(|(a, b)| (a.to_u64(), b.to_u64()));
+
+        // Parties are the connected groups of friends, numbered in scoreboard order
+        let mut party_of: HashMap<SteamID, u32> = HashMap::new();
+        let mut next_party = 1;
+        for player in &self.players {
+            if party_of.contains_key(&player.steamid) {
+                continue;
+            }
+
+            let mut members = vec![player.steamid];
+            let mut i = 0;
+            while i < members.len() {
+                let current = members[i];
+                for (a, b) in &friendships {
+                    let other = if *a == current {
+                        *b
+                    } else if *b == current {
+                        *a
+                    } else {
+                        continue;
+                    };
+                    if !members.contains(&other) {
+                        members.push(other);
+                    }
+                }
+                i += 1;
+            }
+
+            if members.len() > 1 {
+                for member in members {
+                    party_of.insert(member, next_party);
+                }
+                next_party += 1;
+            }
+        }
+
+        for player in &mut self.players {
+            player.party = party_of.get(&player.steamid).copied();
+        }
+        self.friendships = friendships;
+    }
+
+    /// Finds the players whose names look the same on the scoreboard. The oldest
+    /// account in each group keeps the name, the newer ones are suspected impostors.
+    /// Accounts are compared by creation date if all of them are known, otherwise
+    /// by SteamID, as newer accounts get higher SteamIDs.
+    pub fn update_impostors(&mut self) {
+        let mut groups: HashMap<String, Vec<&Player>> = HashMap::new();
+        for player in &self.players {
+            groups
+                .entry(normalize_name(&player.name))
+                .or_default()
+                .push(player);
+        }
+
+        let mut same_names: HashMap<SteamID, (Vec<SteamID>, bool)> = HashMap::new();
+        for group in groups.values().filter(|group| group.len() > 1) {
+            let ages: Option<Vec<DateTime<Local>>> = group
+                .iter()
+                .map(|p| p.steam_info.as_ref().and_then(|info| info.account_age))
+                .collect();
+
+            let original = match ages {
+                Some(ages) => group
+                    .iter()
+                    .zip(ages)
+                    .min_by_key(|(_, age)| *age)
+                    .map(|(p, _)| p.steamid),
+                None => group
+                    .iter()
+                    .min_by_key(|p| p.steamid.to_u64())
+                    .map(|p| p.steamid),
+            };
+
+            for player in group {
+                let others = group
+                    .iter()
+                    .map(|p| p.steamid)
+                    .filter(|steamid| *steamid != player.steamid)
+                    .collect();
+                same_names.insert(player.steamid, (others, Some(player.steamid) != original));
+            }
+        }
+
+        for player in &mut self.players {
+            let (others, impostor) = same_names.remove(&player.steamid).unwrap_or_default();
+            player.same_name_as = others;
+            player.suspected_impostor = impostor;
+        }
+    }
+
+    /// The players a name from the log could belong to. Exact matches are used
+    /// if there are any, otherwise the names that look the same.
+    pub fn find_by_name(&self, name: &str) -> Vec<SteamID> {
+        let exact: Vec<SteamID> = self
+            .players
+            .iter()
+            .filter(|player| player.name == name)
+            .map(|player| player.steamid)
+            .collect();
+        if !exact.is_empty() {
+            return exact;
+        }
+
+        let normalized = normalize_name(name);
+        self.players
+            .iter()
+            .filter(|player| normalize_name(&player.name) == normalized)
+            .map(|player| player.steamid)
+            .collect()
+    }
+
+    /// The players in the lobby that are friends with the player
+    pub fn friends_of(&self, steamid: SteamID) -> Vec<&Player> {
+        self.friendships
+            .iter()
+            .filter_map(|(a, b)| {
+                if *a == steamid {
+                    Some(*b)
+                } else if *b == steamid {
+                    Some(*a)
+                } else {
+                    None
+                }
+            })
+            .filter_map(|friend| self.get_player(None, Some(friend)))
+            .collect()
+    }
+}
+
+fn split_with_roster(list: &str, names: &[&str]) -> Vec<String> {
+    if list.is_empty() {
+        return Vec::new();
+    }
+
+    // The rest of the list after a name, if the name is followed by ", " or the end
+    let after = |name: &str| {
+        let rest = list.strip_prefix(name)?;
+        if rest.is_empty() {
+            Some(rest)
+        } else {
+            rest.strip_prefix(", ")
+        }
+    };
+
+    // Try the known names, and keep the first one that lets the rest be split too
+    for name in names {
+        if let Some(rest) = after(name) {
+            let tail = split_with_roster(rest, names);
+            if tail.iter().all(|n| names.contains(&n.as_str())) {
+                return [vec![name.to_string()], tail].concat();
+            }
+        }
+    }
+
+    // Unknown player, take everything up to the next separator
+    let (name, rest) = list.split_once(", ").unwrap_or((list, ""));
+    [vec![name.to_string()], split_with_roster(rest, names)].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player_with_friends(id: u64, friends: Option<FriendList>) -> Player {
+        let steamid = SteamID::from_u64(id);
+        let mut player = Player::new(id as u32, steamid, format!("Player{}", id), Local::now());
+        player.steam_info = Some(PlayerSteamInfo {
+            steamid,
+            name: player.name.clone(),
+            avatar: String::new(),
+            avatarmedium: String::new(),
+            avatarfull: String::new(),
+            account_age: None,
+            bans: None,
+            tf2_playtime: None,
+            friends,
+        });
+        player
+    }
+
+    fn public(ids: &[u64]) -> Option<FriendList> {
+        Some(FriendList::Public(
+            ids.iter().map(|id| SteamID::from_u64(*id)).collect(),
+        ))
+    }
+
+    #[test]
+    fn test_update_impostors() {
+        let mut lobby = Lobby::new();
+        let mut real = player_with_friends(76561197960435530, None);
+        real.name = "Player1".to_string();
+        let mut copy = player_with_friends(76561199179248685, None);
+        copy.name = "Р\u{200B}layer1".to_string();
+        let other = player_with_friends(76561197994359533, None);
+        lobby.players = vec![copy, real, other];
+
+        // Without account ages, the higher SteamID is newer
+        lobby.update_impostors();
+        let real_id = SteamID::from_u64(76561197960435530);
+        let copy_id = SteamID::from_u64(76561199179248685);
+        assert_eq!(lobby.players[0].same_name_as, vec![real_id]);
+        assert!(lobby.players[0].suspected_impostor);
+        assert_eq!(lobby.players[1].same_name_as, vec![copy_id]);
+        assert!(!lobby.players[1].suspected_impostor);
+        assert!(lobby.players[2].same_name_as.is_empty());
+
+        // An account created earlier keeps the name
+        let now = Local::now();
+        lobby.players[0].steam_info.as_mut().unwrap().account_age =
+            Some(now - chrono::Duration::days(3000));
+        lobby.players[1].steam_info.as_mut().unwrap().account_age = Some(now);
+        lobby.update_impostors();
+        assert!(!lobby.players[0].suspected_impostor);
+        assert!(lobby.players[1].suspected_impostor);
+
+        // The impostor renames, nothing collides anymore
+        lobby.players[0].name = "Someone else".to_string();
+        lobby.update_impostors();
+        assert!(lobby.players.iter().all(|p| p.same_name_as.is_empty()));
+        assert!(!lobby.players[1].suspected_impostor);
+    }
+
+    #[test]
+    fn test_find_by_name() {
+        let mut lobby = Lobby::new();
+        let mut real = player_with_friends(1, None);
+        real.name = "Player1".to_string();
+        let mut copy = player_with_friends(2, None);
+        copy.name = "Player1\u{200B}".to_string();
+        lobby.players = vec![real, copy];
+
+        let (real_id, copy_id) = (SteamID::from_u64(1), SteamID::from_u64(2));
+        assert_eq!(lobby.find_by_name("Player1"), vec![real_id]);
+        assert_eq!(lobby.find_by_name("Player1\u{200B}"), vec![copy_id]);
+
+        // A name mangled in the log could be either
+        assert_eq!(lobby.find_by_name("Player1 "), vec![real_id, copy_id]);
+        assert!(lobby.find_by_name("Player2").is_empty());
+    }
+
+    #[test]
+    fn test_rename() {
+        let first = Local::now();
+        let later = first + chrono::Duration::seconds(10);
+        let mut player = Player::new(1, SteamID::from_u64(1), "Player1".to_string(), first);
+
+        assert!(!player.rename(first, "Player1"));
+        assert!(player.rename(first, "Player2"));
+        assert!(player.rename(later, "Player1"));
+
+        assert_eq!(player.name, "Player1");
+        assert_eq!(player.renames, 2);
+        assert_eq!(
+            player.previous_names,
+            vec![
+                PreviousName {
+                    name: "Player1".to_string(),
+                    until: first
+                },
+                PreviousName {
+                    name: "Player2".to_string(),
+                    until: later
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_connected_str() {
+        let mut player = Player::new(1, SteamID::from_u64(1), "Player1".to_string(), Local::now());
+
+        player.connected = Duration::seconds(5 * 60 + 7);
+        assert_eq!(player.connected_str(), "05:07");
+
+        player.connected = Duration::seconds(2 * 3600 + 3 * 60 + 4);
+        assert_eq!(player.connected_str(), "2:03:04");
+    }
+
+    #[test]
+    fn test_player_left() {
+        let mut lobby = Lobby::new();
+        let when = Local::now();
+        for id in 0..(MAX_RECENTLY_LEFT as u64 + 2) {
+            let steamid = SteamID::from_u64(id);
+            let mut player = Player::new(id as u32, steamid, format!("Player{}", id), when);
+            player.kills = id as u32;
+            lobby.players.push(player);
+        }
+
+        for id in 0..(MAX_RECENTLY_LEFT as u64 + 1) {
+            lobby.player_left(SteamID::from_u64(id), when, "Disconnect by user.");
+        }
+
+        // The oldest is dropped
+        assert_eq!(lobby.players.len(), 1);
+        assert_eq!(lobby.recently_left.len(), MAX_RECENTLY_LEFT);
+        assert_eq!(lobby.recently_left[0].player.name, "Player1");
+
+        // Stats are kept when rejoining
+        let player = lobby.take_recently_left(SteamID::from_u64(5)).unwrap();
+        assert_eq!(player.kills, 5);
+        assert!(lobby.take_recently_left(SteamID::from_u64(5)).is_none());
+        assert!(lobby.take_recently_left(SteamID::from_u64(0)).is_none());
+    }
+
+    #[test]
+    fn test_vote() {
+        let mut lobby = Lobby::new();
+        let now = Local::now();
+        assert!(!lobby.vote_in_progress(now));
+
+        let mut vote = Vote::new(now, "Player1", "Player2", None);
+        assert!(vote.cast("Player1", true));
+        assert!(vote.cast("Player3", false));
+        assert!(!vote.cast("Player1", false));
+        assert_eq!((vote.yes, vote.no), (1, 1));
+        lobby.vote = Some(vote);
+
+        assert!(lobby.vote_in_progress(now));
+        assert!(!lobby.vote_in_progress(now + Duration::seconds(VOTE_TIMEOUT_SECS)));
+
+        lobby.vote.as_mut().unwrap().result = Some(VoteResult::Passed);
+        assert!(!lobby.vote_in_progress(now));
+
+        lobby.vote_cooldown_until = Some(now + Duration::seconds(60));
+        assert_eq!(lobby.vote_cooldown_secs(now), Some(60));
+        assert_eq!(lobby.vote_cooldown_secs(now + Duration::seconds(60)), None);
+    }
+
+    #[test]
+    fn test_split_names() {
+        let mut lobby = Lobby::new();
+        for (id, name) in [
+            "Benito Tortellini (real)",
+            "Hello, World",
+            "Hello",
+            "TopG_14",
+        ]
+        .iter()
+        .enumerate()
+        {
+            lobby.players.push(Player::new(
+                id as u32,
+                SteamID::from_u64(id as u64),
+                name.to_string(),
+                Local::now(),
+            ));
+        }
+
+        assert_eq!(
+            lobby.split_names("Benito Tortellini (real), Hello, World, TopG_14"),
+            vec!["Benito Tortellini (real)", "Hello, World", "TopG_14"]
+        );
+
+        assert_eq!(
+            lobby.split_names("Hello, World, Hello"),
+            vec!["Hello, World", "Hello"]
+        );
+
+        // "Hello, World" would leave an unknown "Again", so "Hello" and "World, Again" wins
+        lobby.players.push(Player::new(
+            9,
+            SteamID::from_u64(9),
+            "World, Again".to_string(),
+            Local::now(),
+        ));
+        assert_eq!(
+            lobby.split_names("Hello, World, Again"),
+            vec!["Hello", "World, Again"]
+        );
+        assert_eq!(lobby.split_names("Hello"), vec!["Hello"]);
+
+        // Unknown players are split on the separator
+        assert_eq!(
+            lobby.split_names("Someone, Hello, Someone else"),
+            vec!["Someone", "Hello", "Someone else"]
+        );
+        assert!(lobby.split_names("").is_empty());
+    }
+
+    #[test]
+    fn test_map_change() {
+        let mut server = ServerInfo::default();
+        let first = Local::now();
+        let later = first + chrono::Duration::minutes(30);
+
+        assert!(server.set_map(first, "pl_badwater"));
+        assert!(!server.set_map(later, "pl_badwater"));
+        assert_eq!(server.map_started, Some(first));
+
+        assert!(server.set_map(later, "cp_process_final"));
+        assert_eq!(server.map, "cp_process_final");
+        assert_eq!(server.map_started, Some(later));
+    }
+
+    #[test]
+    fn test_update_friendships() {
+        let mut lobby = Lobby::new();
+        lobby.players = vec![
+            player_with_friends(1, public(&[2, 100])),
+            // Private list, but player 1 and 3 lists have this player
+            player_with_friends(2, Some(FriendList::Private)),
+            player_with_friends(3, public(&[2])),
+            player_with_friends(4, None),
+            player_with_friends(5, public(&[6])),
+            player_with_friends(6, public(&[5])),
+        ];
+
+        lobby.update_friendships();
+
+        let id = SteamID::from_u64;
+        assert_eq!(
+            lobby.friendships,
+            vec![(id(1), id(2)), (id(2), id(3)), (id(5), id(6))]
+        );
+
+        let parties: Vec<Option<u32>> = lobby.players.iter().map(|p| p.party).collect();
+        assert_eq!(
+            parties,
+            vec![Some(1), Some(1), Some(1), None, Some(2), Some(2)]
+        );
+
+        let friends: Vec<&str> = lobby
+            .friends_of(id(2))
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect();
+        assert_eq!(friends, vec!["Player1", "Player3"]);
+
+        // Players that leave are no longer in a party
+        lobby.players.retain(|p| p.steamid != id(2));
+        lobby.update_friendships();
+        assert_eq!(lobby.friendships, vec![(id(5), id(6))]);
+        assert_eq!(lobby.players[0].party, None);
+    }
 }
@@ -0,0 +1,150 @@
+// Name stealing bots copy the name of a real player in the server, and make it
+// unique with invisible characters, look-alike letters from other alphabets or
+// odd whitespace. normalize_name() strips those, so names that look the same on
+// the scoreboard compare equal.
+
+/// Characters that take no space, or only change the text direction
+fn is_invisible(c: char) -> bool {
+    matches!(c,
+        '\u{00AD}'                  // soft hyphen
+        | '\u{034F}'                // combining grapheme joiner
+        | '\u{061C}'                // arabic letter mark
+        | '\u{115F}' | '\u{1160}'   // hangul fillers
+        | '\u{17B4}' | '\u{17B5}'   // khmer vowel inherent
+        | '\u{180B}'..='\u{180E}'   // mongolian variation selectors and vowel separator
+        | '\u{200B}'..='\u{200F}'   // zero width space, joiners and direction marks
+        | '\u{202A}'..='\u{202E}'   // direction embedding and overrides
+        | '\u{2060}'..='\u{206F}'   // word joiner, invisible operators and direction isolates
+        | '\u{3164}'                // hangul filler
+        | '\u{FE00}'..='\u{FE0F}'   // variation selectors
+        | '\u{FEFF}'                // zero width no-break space
+        | '\u{FFA0}'                // halfwidth hangul filler
+        | '\u{E0000}'..='\u{E007F}' // tags
+    )
+}
+
+/// Letters from other alphabets that look like latin letters
+fn homoglyph(c: char) -> Option<char> {
+    let latin = match c {
+        // Cyrillic
+        'а' => 'a',
+        'в' => 'B',
+        'е' | 'ё' => 'e',
+        'і' => 'i',
+        'ј' => 'j',
+        'к' => 'k',
+        'м' => 'M',
+        'н' => 'H',
+        'о' => 'o',
+        'р' => 'p',
+        'с' => 'c',
+        'т' => 'T',
+        'у' => 'y',
+        'х' => 'x',
+        'ѕ' => 's',
+        'ԁ' => 'd',
+        'ԛ' => 'q',
+        'ԝ' => 'w',
+        'А' => 'A',
+        'В' => 'B',
+        'Е' | 'Ё' => 'E',
+        'І' => 'I',
+        'Ј' => 'J',
+        'К' => 'K',
+        'М' => 'M',
+        'Н' => 'H',
+        'О' => 'O',
+        'Р' => 'P',
+        'С' => 'C',
+        'Т' => 'T',
+        'У' => 'Y',
+        'Х' => 'X',
+        'Ѕ' => 'S',
+        // Greek
+        'α' => 'a',
+        'ο' => 'o',
+        'ν' => 'v',
+        'ρ' => 'p',
+        'τ' => 't',
+        'υ' => 'u',
+        'Α' => 'A',
+        'Β' => 'B',
+        'Ε' => 'E',
+        'Ζ' => 'Z',
+        'Η' => 'H',
+        'Ι' => 'I',
+        'Κ' => 'K',
+        'Μ' => 'M',
+        'Ν' => 'N',
+        'Ο' => 'O',
+        'Ρ' => 'P',
+        'Τ' => 'T',
+        'Υ' => 'Y',
+        'Χ' => 'X',
+        // Latin look-alikes
+        'ı' => 'i',
+        'ɡ' => 'g',
+        'ℓ' => 'l',
+        // Fullwidth ASCII
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0)?,
+        _ => return None,
+    };
+
+    Some(latin)
+}
+
+/// The name as it looks on the scoreboard. Invisible characters are removed,
+/// look-alike letters are made latin and whitespace is collapsed to one space.
+/// A capital I looks like a small l, so both become l.
+pub fn normalize_name(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len());
+    let mut space = false;
+
+    for c in name.chars().filter(|c| !is_invisible(*c)) {
+        if c.is_whitespace() {
+            space = true;
+            continue;
+        }
+
+        if space && !normalized.is_empty() {
+            normalized.push(' ');
+        }
+        space = false;
+
+        match homoglyph(c).unwrap_or(c) {
+            'I' => normalized.push('l'),
+            c => normalized.push(c),
+        }
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_name() {
+        assert_eq!(normalize_name("Player1"), "Player1");
+
+        // Zero width characters
+        assert_eq!(normalize_name("Player1\u{200B}"), "Player1");
+        assert_eq!(normalize_name("\u{2060}Pla\u{200D}yer1\u{FEFF}"), "Player1");
+
+        // Cyrillic and Greek look-alikes
+        assert_eq!(normalize_name("Рlауеr1"), "Player1");
+        assert_eq!(normalize_name("Ρlayer1"), "Player1");
+        assert_eq!(normalize_name("Ｐｌａｙｅｒ１"), "Player1");
+
+        // Whitespace
+        assert_eq!(normalize_name(" Big\u{00A0}\u{3000} Boss "), "Big Boss");
+
+        // I and l
+        assert_eq!(normalize_name("Ivan"), normalize_name("lvan"));
+
+        // Different names stay different
+        assert_ne!(normalize_name("Player1"), normalize_name("Player2"));
+        assert_ne!(normalize_name("player1"), normalize_name("Player1"));
+    }
+}
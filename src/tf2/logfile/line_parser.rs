@@ -1,15 +1,57 @@
-use super::LogLine;
-use chrono::{offset::LocalResult, prelude::*};
-use regex::Regex;
+use super::{KillSplit, LogLine, ServerInfoLine};
+use chrono::{offset::LocalResult, prelude::*, Duration};
+use regex::{Regex, RegexSet};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashSet,
+};
 
-const TIMESTAMP_LEN: usize = 23;
+/// Parses one kind of line, once the prefilter says the line could be that kind
+type ParseFn = fn(&LogLineParser, DateTime<Local>, &str) -> Option<LogLine>;
 
 pub struct LogLineParser {
+    /// All the line patterns in one set, so each line is matched against
+    /// all of them in a single pass
+    prefilter: RegexSet,
+
+    /// The parse function for each pattern in the prefilter
+    rules: Vec<ParseFn>,
+
     killed_rx: Regex,
     lobby_debug_rx: Regex,
     suicided_rx: Regex,
     player_status_rx: Regex,
     chat_rx: Regex,
+    server_info_rx: Regex,
+    name_change_rx: Regex,
+    connected_rx: Regex,
+    left_rx: Regex,
+    dropped_rx: Regex,
+    captured_rx: Regex,
+    defended_rx: Regex,
+    server_players_rx: Regex,
+    vote_started_rx: Regex,
+    vote_cast_rx: Regex,
+    vote_ended_rx: Regex,
+    vote_cooldown_rx: Regex,
+    item_found_rx: Regex,
+    server_message_rx: Regex,
+    achievement_rx: Regex,
+
+    /// Names of the players in the server, to split kill lines with
+    roster: HashSet<String>,
+
+    /// The con_timestamp at the start of a line, like "05/06/2024 - 17:02:55: "
+    timestamp_rx: Regex,
+
+    /// The text of the last timestamp seen. Lines without one get this time.
+    last_timestamp: RefCell<String>,
+
+    /// The last timestamp parsed, lines written in the same second share it
+    last_when: RefCell<Option<(String, DateTime<Local>)>>,
+
+    /// True if the log has the day before the month. None until a line shows it.
+    day_first: Cell<Option<bool>>,
 }
 
 impl Default for LogLineParser {
@@ -20,95 +62,328 @@ impl Default for LogLineParser {
 
 impl LogLineParser {
     pub fn new() -> Self {
-        let player_statys_str = {
-            let id = r"\d{1,6}";
-            let name = r".+?";
-            let steamid32 = r"\[U:\d:\d{1,10}]";
-            let time = r"\d{1,3}:\d{2}(:\d{2})?";
-            let ping = r"\d{1,4}";
-            let loss = r"\d{1,3}";
-            let state = r"(spawning|active)";
+        let player_status = {
+            let id = r"(?P<id>\d{1,6})";
+            let name = r"(?P<name>.+?)";
+            let steamid32 = r"(?P<steamid32>\[U:\d:\d{1,10}])";
+            let time = r"(?P<time>\d{1,3}:\d{2}(:\d{2})?)";
+            let ping = r"(?P<ping>\d{1,4})";
+            let loss = r"(?P<loss>\d{1,3})";
+            let state = r"(?P<state>spawning|active)";
             format!(
-                r#"^#\s{{1,6}}({id}) "({name})"\s+({steamid32})\s{{1,8}}{time}\s+{ping}\s{{1,8}}{loss} {state}$"#
+                r#"^#\s{{1,6}}{id} "{name}"\s+{steamid32}\s{{1,8}}{time}\s+{ping}\s{{1,8}}{loss} {state}$"#
             )
         };
 
-        let lobby_debug_rx = r#"^\s{2}(Member|Pending)\[\d+]\s+(?P<sid>\[.+?]).+?TF_GC_TEAM_(?P<team>(DEFENDERS|INVADERS))\s{2}type\s=\sMATCH_PLAYER$"#;
+        let lobby_debug = r#"^\s{2}(Member|Pending)\[\d+]\s+(?P<sid>\[.+?]).+?TF_GC_TEAM_(?P<team>(DEFENDERS|INVADERS))\s{2}type\s=\sMATCH_PLAYER$"#;
+        let killed = r"^(.+ killed .+ with .+?)(\. \(crit\)|\.)$";
+        let status_header = r"^# userid name\s+uniqueid\s+connected ping loss state$";
+        // The padding is exactly as TF2 prints it, so players named "map" etc can chat
+        let server_info = r"^(?P<key>hostname|version |udp/ip  |steamid |map     |tags    |players ): (?P<value>.*)$";
+        let lobby_status = r"^Lobby (created|destroyed)$";
+        let chat = r"^(.+?) :  (.+)$";
+        let name_change = r"^(.+?) changed name to (.+)$";
+        let captured = r"^(.+?) captured (.+) for team #(\d+)$";
+        let defended = r"^(.+?) defended (.+) for team #(\d+)$";
+        let connected = r"^(.+) connected$";
+        let left = r"^(.+) left the game \((.*)\)$";
+        let dropped = r"^Dropped (.+) from server \((.*)\)$";
+        let vote_started = r"^(.+?) called a vote to kick (.+)$";
+        let vote_cast = r"^(.+) voted (Yes|No)$";
+        let vote_ended = r"^Vote (passed|failed)(?:\.|: (.*))$";
+        let vote_cooldown = r"^You cannot call a (?:new )?vote (?:yet|for (\d+) seconds?)\.?$";
+        // Crafted, traded etc items are found items too, they all take a real account
+        let item_found =
+            r"^(.+?) has (?:found|crafted|unboxed|traded for|received a gift|earned):\s+(.+)$";
+        let achievement = r"^(.+?) has earned the achievement (.+)$";
+        let server_message = r"^\[SM\] (.+)$";
+        let suicided = r"^(.+?) suicided.$";
+
+        // When more than one pattern matches, the first one that parses wins
+        let rules: Vec<(&str, ParseFn)> = vec![
+            (lobby_debug, Self::parse_tf_lobby_debug_line),
+            (status_header, Self::parse_status_header_line),
+            (&player_status, Self::parse_status_player_line),
+            (lobby_status, Self::parse_lobby_status_line),
+            (chat, Self::parse_chat_line),
+            // After chat, so chat messages can't look like these
+            (server_message, Self::parse_server_message_line),
+            (killed, Self::parse_killed_line),
+            (server_info, Self::parse_server_info_line),
+            (name_change, Self::parse_name_change_line),
+            (captured, Self::parse_objective_line),
+            (defended, Self::parse_objective_line),
+            (connected, Self::parse_connection_line),
+            (left, Self::parse_connection_line),
+            (dropped, Self::parse_connection_line),
+            (vote_started, Self::parse_vote_line),
+            (vote_cast, Self::parse_vote_line),
+            (vote_ended, Self::parse_vote_line),
+            (vote_cooldown, Self::parse_vote_line),
+            (item_found, Self::parse_item_line),
+            (achievement, Self::parse_item_line),
+            (suicided, Self::parse_suicided_line),
+        ];
 
         Self {
-            killed_rx: Regex::new(r"^(.+?) killed (.+?) with (.+)(\.|\. \(crit\))$").unwrap(),
-            suicided_rx: Regex::new(r"^(.+?) suicided.$").unwrap(),
-            player_status_rx: Regex::new(player_statys_str.as_str()).unwrap(),
-            lobby_debug_rx: Regex::new(lobby_debug_rx).unwrap(),
-            chat_rx: Regex::new(r"^(.+?) :  (.+)$").unwrap(),
+            prefilter: RegexSet::new(rules.iter().map(|(pattern, _)| pattern)).unwrap(),
+            rules: rules.iter().map(|(_, parse)| *parse).collect(),
+            killed_rx: Regex::new(killed).unwrap(),
+            suicided_rx: Regex::new(suicided).unwrap(),
+            player_status_rx: Regex::new(&player_status).unwrap(),
+            lobby_debug_rx: Regex::new(lobby_debug).unwrap(),
+            chat_rx: Regex::new(chat).unwrap(),
+            server_info_rx: Regex::new(server_info).unwrap(),
+            name_change_rx: Regex::new(name_change).unwrap(),
+            connected_rx: Regex::new(connected).unwrap(),
+            left_rx: Regex::new(left).unwrap(),
+            dropped_rx: Regex::new(dropped).unwrap(),
+            captured_rx: Regex::new(captured).unwrap(),
+            defended_rx: Regex::new(defended).unwrap(),
+            server_players_rx: Regex::new(r"^(\d+) humans, (\d+) bots \((\d+) max\)$").unwrap(),
+            vote_started_rx: Regex::new(vote_started).unwrap(),
+            vote_cast_rx: Regex::new(vote_cast).unwrap(),
+            vote_ended_rx: Regex::new(vote_ended).unwrap(),
+            vote_cooldown_rx: Regex::new(vote_cooldown).unwrap(),
+            item_found_rx: Regex::new(item_found).unwrap(),
+            achievement_rx: Regex::new(achievement).unwrap(),
+            server_message_rx: Regex::new(server_message).unwrap(),
+            roster: HashSet::new(),
+            // Month and day can be in either order, see is_day_first(), and the year can come first
+            timestamp_rx: Regex::new(
+                r"^(?P<a>\d{1,4})[/.-](?P<b>\d{1,2})[/.-](?P<c>\d{1,4}) - (?P<hour>\d{1,2}):(?P<min>\d{2}):(?P<sec>\d{2}): ?",
+            )
+            .unwrap(),
+            last_timestamp: RefCell::new(String::new()),
+            last_when: RefCell::new(None),
+            day_first: Cell::new(None),
         }
     }
 
-    pub fn parse_line(&self, org_line: &str) -> Option<LogLine> {
-        let when = self.try_get_when(org_line);
-        if when.is_some() {
-            let when = when.unwrap();
-            let line = &org_line[TIMESTAMP_LEN..];
-
-            let logobj = self.parse_tf_lobby_debug_line(when, line);
-            if logobj.is_some() {
-                return logobj;
+    /// Keeps the roster up to date from the lines parsed so far.
+    /// The status output lists everyone, so it starts the roster over.
+    pub fn update_roster(&mut self, logline: &LogLine) {
+        match logline {
+            LogLine::StatusHeader { .. } => self.roster.clear(),
+            LogLine::StatusForPlayer { name, .. } | LogLine::Connected { name, .. } => {
+                self.roster.insert(name.clone());
             }
-
-            let logobj = self.parse_killed_line(when, line);
-            if logobj.is_some() {
-                return logobj;
+            LogLine::Disconnected { name, .. } => {
+                self.roster.remove(name);
             }
-
-            let logobj = self.parse_status_header_line(when, line);
-            if logobj.is_some() {
-                return logobj;
-            }
-
-            let logobj = self.parse_status_player_line(when, line);
-            if logobj.is_some() {
-                return logobj;
+            LogLine::NameChange {
+                old_name, new_name, ..
+            } => {
+                self.roster.remove(old_name);
+                self.roster.insert(new_name.clone());
             }
+            _ => {}
+        }
+    }
 
-            let logobj = self.parse_lobby_status_line(when, line);
-            if logobj.is_some() {
-                return logobj;
+    pub fn parse_line(&self, org_line: &str) -> Option<LogLine> {
+        let line = match self.timestamp_rx.find(org_line) {
+            Some(timestamp) => {
+                let mut last_timestamp = self.last_timestamp.borrow_mut();
+                last_timestamp.clear();
+                last_timestamp.push_str(timestamp.as_str());
+                &org_line[timestamp.end()..]
             }
+            None => org_line,
+        };
 
-            let logobj = self.parse_chat_line(when, line);
-            if logobj.is_some() {
-                return logobj;
-            }
+        // Most lines are none of ours, so they are thrown out before the timestamp is parsed
+        let matches = self.prefilter.matches(line);
+        if !matches.matched_any() {
+            return None;
+        }
 
-            let logobj = self.parse_suicided_line(when, line);
-            if logobj.is_some() {
-                return logobj;
-            }
+        // Lines without a timestamp happened at the time of the line before them.
+        // Before the first timestamp the time isn't known, so only the
+        // tf_lobby_debug lines are kept, they don't need one.
+        let last_timestamp = self.last_timestamp.borrow().clone();
+        if last_timestamp.is_empty() {
+            return self.lobby_debug_team(line);
         }
+        let when = self.parse_timestamp(&last_timestamp)?;
 
-        None
+        matches
+            .into_iter()
+            .find_map(|index| (self.rules[index])(self, when, line))
     }
 
+    /// Names can contain " killed " and " with ", so a kill line can be split in
+    /// more than one way. Every split is tried, and if more than one is possible
+    /// the one where both the killer and the victim are in the roster is used.
+    /// If that doesn't settle it, the line is reported as ambiguous.
     pub fn parse_killed_line(&self, when: DateTime<Local>, line: &str) -> Option<LogLine> {
-        let caps = self.killed_rx.captures(line);
+        let caps = self.killed_rx.captures(line)?;
+        let crit = &caps[2] == ". (crit)";
+
+        let mut splits = kill_splits(&caps[1]);
+        if splits.len() > 1 {
+            let known: Vec<KillSplit> = splits
+                .iter()
+                .filter(|split| {
+                    self.roster.contains(&split.killer) && self.roster.contains(&split.victim)
+                })
+                .cloned()
+                .collect();
 
-        match caps {
-            Some(caps) => {
-                let killer = caps.get(1).unwrap().as_str().to_string();
-                let victim = caps.get(2).unwrap().as_str().to_string();
-                let weapon = caps.get(3).unwrap().as_str().to_string();
-                let crit = caps.get(4).unwrap().as_str().ends_with(". (crit)");
+            if known.len() == 1 {
+                splits = known;
+            }
+        }
 
+        match splits.len() {
+            0 => None,
+            1 => {
+                let split = splits.remove(0);
                 Some(LogLine::Kill {
                     when,
-                    killer,
-                    victim,
-                    weapon,
+                    killer: split.killer,
+                    victim: split.victim,
+                    weapon: split.weapon,
                     crit,
                 })
             }
-            None => None,
+            _ => Some(LogLine::AmbiguousKill { when, splits, crit }),
+        }
+    }
+
+    /// Names can have " changed name to " in them. The old name is a player in the
+    /// server, so when the line can be split more than one way the roster decides.
+    /// If it can't, the first split is used.
+    pub fn parse_name_change_line(&self, when: DateTime<Local>, line: &str) -> Option<LogLine> {
+        const CHANGED: &str = " changed name to ";
+
+        if !self.name_change_rx.is_match(line) {
+            return None;
+        }
+
+        let splits: Vec<(&str, &str)> = positions(line, CHANGED)
+            .into_iter()
+            .map(|i| (&line[..i], &line[i + CHANGED.len()..]))
+            .filter(|(old_name, new_name)| !old_name.is_empty() && !new_name.is_empty())
+            .collect();
+
+        let known: Vec<&(&str, &str)> = splits
+            .iter()
+            .filter(|(old_name, _)| self.roster.contains(*old_name))
+            .collect();
+        let (old_name, new_name) = match known[..] {
+            [split] => *split,
+            _ => *splits.first()?,
+        };
+
+        Some(LogLine::NameChange {
+            when,
+            old_name: old_name.to_string(),
+            new_name: new_name.to_string(),
+        })
+    }
+
+    pub fn parse_connection_line(&self, when: DateTime<Local>, line: &str) -> Option<LogLine> {
+        if let Some(caps) = self.connected_rx.captures(line) {
+            return Some(LogLine::Connected {
+                when,
+                name: caps[1].to_string(),
+            });
         }
+
+        let caps = self
+            .dropped_rx
+            .captures(line)
+            .or_else(|| self.left_rx.captures(line))?;
+
+        Some(LogLine::Disconnected {
+            when,
+            name: caps[1].to_string(),
+            reason: caps[2].to_string(),
+        })
+    }
+
+    pub fn parse_vote_line(&self, when: DateTime<Local>, line: &str) -> Option<LogLine> {
+        if let Some(caps) = self.vote_started_rx.captures(line) {
+            return Some(LogLine::VoteStarted {
+                when,
+                caller: caps[1].to_string(),
+                target: caps[2].to_string(),
+            });
+        }
+
+        if let Some(caps) = self.vote_cast_rx.captures(line) {
+            return Some(LogLine::VoteCast {
+                when,
+                name: caps[1].to_string(),
+                yes: &caps[2] == "Yes",
+            });
+        }
+
+        if let Some(caps) = self.vote_ended_rx.captures(line) {
+            return Some(LogLine::VoteEnded {
+                when,
+                passed: &caps[1] == "passed",
+                reason: caps.get(2).map_or("", |m| m.as_str()).to_string(),
+            });
+        }
+
+        let caps = self.vote_cooldown_rx.captures(line)?;
+        Some(LogLine::VoteCooldown {
+            when,
+            seconds: caps.get(1).and_then(|m| m.as_str().parse().ok()),
+        })
+    }
+
+    pub fn parse_item_line(&self, when: DateTime<Local>, line: &str) -> Option<LogLine> {
+        if let Some(caps) = self.item_found_rx.captures(line) {
+            return Some(LogLine::ItemFound {
+                when,
+                name: caps[1].to_string(),
+                item: caps[2].to_string(),
+            });
+        }
+
+        let caps = self.achievement_rx.captures(line)?;
+        Some(LogLine::Achievement {
+            when,
+            name: caps[1].to_string(),
+            achievement: caps[2].to_string(),
+        })
+    }
+
+    /// SourceMod messages are kept with their "[SM]" tag
+    pub fn parse_server_message_line(&self, when: DateTime<Local>, line: &str) -> Option<LogLine> {
+        if !self.server_message_rx.is_match(line) {
+            return None;
+        }
+
+        Some(LogLine::ServerMessage {
+            when,
+            message: line.to_string(),
+        })
+    }
+
+    pub fn parse_objective_line(&self, when: DateTime<Local>, line: &str) -> Option<LogLine> {
+        if let Some(caps) = self.captured_rx.captures(line) {
+            return Some(LogLine::Capture {
+                when,
+                cappers: caps[1].to_string(),
+                point: caps[2].to_string(),
+                team: caps[3].parse().ok()?,
+            });
+        }
+
+        if let Some(caps) = self.defended_rx.captures(line) {
+            return Some(LogLine::Defended {
+                when,
+                name: caps[1].to_string(),
+                point: caps[2].to_string(),
+                team: caps[3].parse().ok()?,
+            });
+        }
+
+        None
     }
 
     pub fn parse_suicided_line(&self, when: DateTime<Local>, line: &str) -> Option<LogLine> {
@@ -127,14 +402,22 @@ impl LogLineParser {
         let caps = self.player_status_rx.captures(line);
         match caps {
             Some(caps) => {
-                let id: u32 = caps.get(1).unwrap().as_str().to_string().parse().unwrap();
-                let name = caps.get(2).unwrap().as_str().to_string();
-                let steam_id32 = caps.get(3).unwrap().as_str().to_string();
+                let id: u32 = caps["id"].parse().unwrap();
+                let name = caps["name"].to_string();
+                let steam_id32 = caps["steamid32"].to_string();
+                let connected = parse_connected_time(&caps["time"]);
+                let ping: u32 = caps["ping"].parse().unwrap();
+                let loss: u32 = caps["loss"].parse().unwrap();
+                let state = caps["state"].to_string();
                 Some(LogLine::StatusForPlayer {
                     when,
                     id,
                     name,
                     steam_id32,
+                    connected,
+                    ping,
+                    loss,
+                    state,
                 })
             }
             None => None,
@@ -197,7 +480,50 @@ impl LogLineParser {
         None
     }
 
+    pub fn parse_server_info_line(&self, when: DateTime<Local>, line: &str) -> Option<LogLine> {
+        let caps = self.server_info_rx.captures(line)?;
+        let value = caps["value"].trim().to_string();
+
+        let info = match caps["key"].trim_end() {
+            "hostname" => ServerInfoLine::Hostname(value),
+            "version" => ServerInfoLine::Version(value),
+            "udp/ip" => ServerInfoLine::UdpIp(value),
+            "steamid" => {
+                // [A:1:509071377:29317] (90197908612698129)
+                let steamid = value.split_whitespace().next().unwrap_or_default();
+                ServerInfoLine::SteamId(steamid.to_string())
+            }
+            "map" => {
+                // pl_badwater at: 0 x, 0 y, 0 z
+                let map = value.split(" at: ").next().unwrap_or_default();
+                ServerInfoLine::Map(map.to_string())
+            }
+            "tags" => ServerInfoLine::Tags(
+                value
+                    .split(',')
+                    .filter(|tag| !tag.is_empty())
+                    .map(|tag| tag.to_string())
+                    .collect(),
+            ),
+            "players" => {
+                let caps = self.server_players_rx.captures(&value)?;
+                ServerInfoLine::Players {
+                    humans: caps[1].parse().ok()?,
+                    bots: caps[2].parse().ok()?,
+                    max: caps[3].parse().ok()?,
+                }
+            }
+            _ => return None,
+        };
+
+        Some(LogLine::ServerInfo { when, info })
+    }
+
     pub fn parse_tf_lobby_debug_line(&self, _when: DateTime<Local>, line: &str) -> Option<LogLine> {
+        self.lobby_debug_team(line)
+    }
+
+    fn lobby_debug_team(&self, line: &str) -> Option<LogLine> {
         let caps = self.lobby_debug_rx.captures(line);
 
         match caps {
@@ -211,34 +537,148 @@ impl LogLineParser {
         }
     }
 
+    /// The time from the con_timestamp at the start of the line, if it has one
     pub fn try_get_when(&self, line: &str) -> Option<DateTime<Local>> {
-        if line.len() < TIMESTAMP_LEN {
-            return None;
+        let timestamp = self.timestamp_rx.find(line)?;
+        self.parse_timestamp(timestamp.as_str())
+    }
+
+    fn parse_timestamp(&self, timestamp: &str) -> Option<DateTime<Local>> {
+        if let Some((last, when)) = self.last_when.borrow().as_ref() {
+            if last == timestamp {
+                return Some(*when);
+            }
         }
 
-        let fmt = "%m/%d/%Y - %H:%M:%S";
+        let caps = self.timestamp_rx.captures(timestamp)?;
+        let number = |name: &str| caps[name].parse::<u32>().ok();
+        let (a, b, c) = (number("a")?, number("b")?, number("c")?);
+
+        let (year, month, day) = if caps["a"].len() == 4 {
+            (a, b, c)
+        } else if self.is_day_first(a, b) {
+            (c, b, a)
+        } else {
+            (c, a, b)
+        };
 
-        let line = &line[0..21];
-        let result = NaiveDateTime::parse_from_str(line, fmt);
+        let naive = NaiveDate::from_ymd_opt(year as i32, month, day)?.and_hms_opt(
+            number("hour")?,
+            number("min")?,
+            number("sec")?,
+        )?;
+
+        let previous = self.last_when.borrow().as_ref().map(|(_, when)| *when);
+        let when = match pick_local_time(Local.from_local_datetime(&naive), previous) {
+            Some(when) => when,
+            // The clocks were turned forward and this time never happened,
+            // it's the time after the gap
+            None => Local
+                .from_local_datetime(&(naive + Duration::hours(1)))
+                .earliest()?,
+        };
 
-        match result {
-            Ok(when) => match Local.from_local_datetime(&when) {
-                LocalResult::Single(when) => Some(when),
-                LocalResult::Ambiguous(_e, _l) => {
-                    // println!("Ambiguous: Error parsing date: {:?}, {:?}", e, l);
-                    None
+        *self.last_when.borrow_mut() = Some((timestamp.to_string(), when));
+        Some(when)
+    }
+
+    /// Looks ahead for the first date that shows whether the day or the month comes first,
+    /// so the lines before it are read the right way too. Called with the text of the log
+    /// before its lines are parsed.
+    pub fn scan_date_order(&self, text: &str) {
+        for line in text.lines() {
+            if self.day_first.get().is_some() {
+                return;
+            }
+
+            if let Some(caps) = self.timestamp_rx.captures(line) {
+                if caps["a"].len() == 4 {
+                    continue;
                 }
-                LocalResult::None => {
-                    // println!("None: Error parsing date");
-                    None
+                if let (Ok(a), Ok(b)) = (caps["a"].parse(), caps["b"].parse()) {
+                    self.is_day_first(a, b);
                 }
-            },
-            Err(_e) => {
-                // println!("Error parsing date: {:?}. Line = '{}'", e, line);
-                None
             }
         }
     }
+
+    /// TF2 writes "%m/%d/%Y", but some systems write the day first. The layout is the same
+    /// for the whole log, so the first line with a number over 12 settles it for the rest,
+    /// see scan_date_order(). Until then the date is read as TF2 writes it.
+    fn is_day_first(&self, a: u32, b: u32) -> bool {
+        if let Some(day_first) = self.day_first.get() {
+            return day_first;
+        }
+
+        let day_first = match (a > 12, b > 12) {
+            (true, false) => true,
+            (false, true) => false,
+            _ => return false,
+        };
+        self.day_first.set(Some(day_first));
+        day_first
+    }
+}
+
+/// When the clocks are turned back a local time happens twice. The one that
+/// doesn't go back in time from the line before is used.
+fn pick_local_time<Tz: TimeZone>(
+    result: LocalResult<DateTime<Tz>>,
+    previous: Option<DateTime<Tz>>,
+) -> Option<DateTime<Tz>> {
+    match result {
+        LocalResult::Single(when) => Some(when),
+        LocalResult::Ambiguous(earliest, latest) => match previous {
+            Some(previous) if previous > earliest => Some(latest),
+            _ => Some(earliest),
+        },
+        LocalResult::None => None,
+    }
+}
+
+/// Where the pattern starts in the text, every place it does
+fn positions(text: &str, pattern: &str) -> Vec<usize> {
+    (0..text.len())
+        .filter(|i| text.is_char_boundary(*i) && text[*i..].starts_with(pattern))
+        .collect()
+}
+
+/// All the ways "<killer> killed <victim> with <weapon>" can be split.
+/// Weapons are kill icon names like "iron_bomber" and never have spaces.
+fn kill_splits(text: &str) -> Vec<KillSplit> {
+    const KILLED: &str = " killed ";
+    const WITH: &str = " with ";
+
+    let mut splits = Vec::new();
+    for killed in positions(text, KILLED) {
+        let killer = &text[..killed];
+        let rest = &text[killed + KILLED.len()..];
+
+        for with in positions(rest, WITH) {
+            let victim = &rest[..with];
+            let weapon = &rest[with + WITH.len()..];
+
+            if killer.is_empty() || victim.is_empty() || weapon.contains(char::is_whitespace) {
+                continue;
+            }
+
+            splits.push(KillSplit {
+                killer: killer.to_string(),
+                victim: victim.to_string(),
+                weapon: weapon.to_string(),
+            });
+        }
+    }
+
+    splits
+}
+
+/// Parses the connected time from status, "mm:ss" or "hh:mm:ss"
+fn parse_connected_time(time: &str) -> Duration {
+    let seconds = time.split(':').fold(0, |total, part| {
+        total * 60 + part.parse::<i64>().unwrap_or(0)
+    });
+    Duration::seconds(seconds)
 }
 
 #[cfg(test)]
@@ -251,7 +691,7 @@ mod tests {
     fn test_get_date() {
         let parser = LogLineParser::default();
 
-        let when = Local.with_ymd_and_hms(2020, 11, 7, 08, 41, 39).unwrap();
+        let when = Local.with_ymd_and_hms(2020, 11, 7, 8, 41, 39).unwrap();
 
         let result = parser.try_get_when("11/07/2020 - 08:41:39: #");
         assert_eq!(result, Some(when));
@@ -260,6 +700,118 @@ mod tests {
         assert_eq!(result, None);
     }
 
+    #[test]
+    fn test_timestamp_layouts() {
+        let when = Local.with_ymd_and_hms(2024, 5, 16, 8, 41, 39).unwrap();
+
+        // Each layout is a log of its own
+        for line in [
+            "05/16/2024 - 08:41:39: Player1 connected",
+            "16/05/2024 - 08:41:39: Player1 connected",
+            "16.05.2024 - 08:41:39: Player1 connected",
+            "2024-05-16 - 08:41:39: Player1 connected",
+            "5/16/2024 - 8:41:39: Player1 connected",
+        ] {
+            let parser = LogLineParser::default();
+            assert_eq!(parser.try_get_when(line), Some(when), "{}", line);
+            assert_eq!(
+                parser.parse_line(line),
+                Some(LogLine::Connected {
+                    when,
+                    name: "Player1".to_string()
+                }),
+                "{}",
+                line
+            );
+        }
+
+        let parser = LogLineParser::default();
+        assert_eq!(parser.try_get_when("13/13/2024 - 08:41:39: x"), None);
+    }
+
+    #[test]
+    fn test_ambiguous_date() {
+        let june = Local.with_ymd_and_hms(2024, 6, 5, 8, 41, 39).unwrap();
+        let may = Local.with_ymd_and_hms(2024, 5, 6, 8, 41, 39).unwrap();
+
+        // Read as TF2 writes it until the log shows otherwise
+        let parser = LogLineParser::default();
+        assert_eq!(parser.try_get_when("06/05/2024 - 08:41:39: x"), Some(june));
+        assert!(parser.try_get_when("05/16/2024 - 08:41:39: x").is_some());
+        assert_eq!(parser.try_get_when("06/05/2024 - 08:41:39: x"), Some(june));
+
+        // A day first log
+        let parser = LogLineParser::default();
+        assert!(parser.try_get_when("16/05/2024 - 08:41:39: x").is_some());
+        assert_eq!(parser.try_get_when("06/05/2024 - 08:41:39: x"), Some(may));
+
+        // The lines before the first day over 12 are read day first too, after a look ahead
+        let log = "06/05/2024 - 08:41:39: Player1 connected\n\
+                   07/05/2024 - 08:41:39: Player2 connected\n\
+                   16/05/2024 - 08:41:39: Player3 connected\n";
+        let parser = LogLineParser::default();
+        parser.scan_date_order(log);
+        let whens: Vec<_> = log
+            .lines()
+            .filter_map(|line| parser.parse_line(line))
+            .filter_map(|logline| logline.when())
+            .map(|when| when.date_naive())
+            .collect();
+        let date = |day| NaiveDate::from_ymd_opt(2024, 5, day).unwrap();
+        assert_eq!(whens, vec![date(6), date(7), date(16)]);
+    }
+
+    #[test]
+    fn test_lines_without_timestamp() {
+        let parser = LogLineParser::default();
+
+        // Short non-ASCII lines don't panic
+        assert_eq!(parser.parse_line("éé"), None);
+        assert_eq!(parser.parse_line(""), None);
+
+        // Before the first timestamp the time isn't known, only lobby_debug lines have no time
+        assert_eq!(parser.parse_line("Player1 connected"), None);
+        assert!(matches!(
+            parser.parse_line(
+                "  Member[23] [U:1:169802]  team = TF_GC_TEAM_DEFENDERS  type = MATCH_PLAYER"
+            ),
+            Some(LogLine::PlayerTeam { .. })
+        ));
+
+        // Without a timestamp, the time of the line before is used
+        let when = Local.with_ymd_and_hms(2024, 5, 6, 17, 5, 50).unwrap();
+        parser.parse_line("05/06/2024 - 17:05:50: Not a line we know");
+        assert_eq!(
+            parser.parse_line("Player1 connected"),
+            Some(LogLine::Connected {
+                when,
+                name: "Player1".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_pick_local_time() {
+        let tz = FixedOffset::east_opt(3600).unwrap();
+        let earliest = tz.with_ymd_and_hms(2024, 10, 27, 2, 30, 0).unwrap();
+        let latest = earliest + Duration::hours(1);
+        let ambiguous = || LocalResult::Ambiguous(earliest, latest);
+
+        assert_eq!(pick_local_time(ambiguous(), None), Some(earliest));
+
+        // After a line in the second 02:30, stay there
+        let previous = earliest + Duration::minutes(50);
+        assert_eq!(pick_local_time(ambiguous(), Some(previous)), Some(latest));
+
+        let previous = earliest - Duration::minutes(10);
+        assert_eq!(pick_local_time(ambiguous(), Some(previous)), Some(earliest));
+
+        assert_eq!(
+            pick_local_time(LocalResult::<DateTime<FixedOffset>>::None, None),
+            None
+        );
+    }
+
     #[test]
     fn test_parse_kill_line() {
         let parser = LogLineParser::default();
@@ -305,6 +857,324 @@ mod tests {
         );
     }
 
+    fn roster(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_kill_line_adversarial_names() {
+        let mut parser = LogLineParser::default();
+        let when = Local.with_ymd_and_hms(2024, 5, 6, 17, 2, 55).unwrap();
+        let kill = |killer: &str, victim: &str| LogLine::Kill {
+            when,
+            killer: killer.to_string(),
+            victim: victim.to_string(),
+            weapon: "scattergun".to_string(),
+            crit: false,
+        };
+
+        // Only one way to read it, the roster isn't needed
+        let line = "05/06/2024 - 17:02:55: a with b killed c with scattergun.";
+        assert_eq!(parser.parse_line(line).unwrap(), kill("a with b", "c"));
+
+        // Without a roster these can't be told apart
+        let line = "05/06/2024 - 17:02:55: a killed b killed c with scattergun.";
+        let Some(LogLine::AmbiguousKill { splits, .. }) = parser.parse_line(line) else {
+            panic!("Expected an ambiguous kill");
+        };
+        assert_eq!(splits.len(), 2);
+
+        parser.roster = roster(&["a killed b", "c"]);
+        assert_eq!(parser.parse_line(line).unwrap(), kill("a killed b", "c"));
+
+        parser.roster = roster(&["a", "b killed c"]);
+        assert_eq!(parser.parse_line(line).unwrap(), kill("a", "b killed c"));
+
+        // Victim with " with " in the name
+        let line = "05/06/2024 - 17:02:55: Player1 killed Player2 with love with scattergun.";
+        assert_eq!(
+            parser.parse_line(line).unwrap(),
+            kill("Player1", "Player2 with love")
+        );
+
+        // Both readings have known players, so it's still ambiguous
+        parser.roster = roster(&["a", "b killed c", "a killed b", "c"]);
+        let line = "05/06/2024 - 17:02:55: a killed b killed c with scattergun. (crit)";
+        assert!(matches!(
+            parser.parse_line(line),
+            Some(LogLine::AmbiguousKill { crit: true, .. })
+        ));
+    }
+
+    #[test]
+    fn test_update_roster() {
+        let mut parser = LogLineParser::default();
+        let lines = [
+            "05/06/2024 - 17:05:50: # userid name                uniqueid            connected ping loss state",
+            r#"05/06/2024 - 17:05:50: #   2802 "x killed y"        [U:1:169802]     34:11       56    0 active"#,
+            "05/06/2024 - 17:05:51: z connected",
+            "05/06/2024 - 17:05:52: z changed name to x",
+        ];
+        for line in lines {
+            let logline = parser.parse_line(line).unwrap();
+            parser.update_roster(&logline);
+        }
+
+        let line = "05/06/2024 - 17:05:53: x killed y killed x with scattergun.";
+        let Some(LogLine::Kill { killer, victim, .. }) = parser.parse_line(line) else {
+            panic!("Expected a kill");
+        };
+        assert_eq!((killer.as_str(), victim.as_str()), ("x killed y", "x"));
+    }
+
+    #[test]
+    fn test_parse_name_change_line() {
+        let mut parser = LogLineParser::default();
+
+        let when = Local.with_ymd_and_hms(2024, 5, 6, 17, 10, 2).unwrap();
+        let line = "05/06/2024 - 17:10:02: Player1 changed name to (1)Player 2";
+        let result = parser.parse_line(line).unwrap();
+        assert_eq!(
+            result,
+            LogLine::NameChange {
+                when,
+                old_name: "Player1".to_string(),
+                new_name: "(1)Player 2".to_string(),
+            }
+        );
+
+        // The old name has the separator in it
+        let line = "05/06/2024 - 17:10:02: a changed name to b changed name to c";
+        let split = |old_name: &str, new_name: &str| LogLine::NameChange {
+            when,
+            old_name: old_name.to_string(),
+            new_name: new_name.to_string(),
+        };
+        assert_eq!(
+            parser.parse_line(line),
+            Some(split("a", "b changed name to c"))
+        );
+
+        parser.roster = roster(&["a changed name to b"]);
+        assert_eq!(
+            parser.parse_line(line),
+            Some(split("a changed name to b", "c"))
+        );
+    }
+
+    #[test]
+    fn test_parse_connection_line() {
+        let parser = LogLineParser::default();
+        let when = Local.with_ymd_and_hms(2024, 5, 6, 17, 20, 40).unwrap();
+
+        let line = "05/06/2024 - 17:20:40: Player1 connected";
+        let result = parser.parse_line(line).unwrap();
+        assert_eq!(
+            result,
+            LogLine::Connected {
+                when,
+                name: "Player1".to_string(),
+            }
+        );
+
+        let disconnected = LogLine::Disconnected {
+            when,
+            name: "Player1".to_string(),
+            reason: "Disconnect by user.".to_string(),
+        };
+
+        let line = "05/06/2024 - 17:20:40: Player1 left the game (Disconnect by user.)";
+        assert_eq!(parser.parse_line(line).unwrap(), disconnected);
+
+        let line = "05/06/2024 - 17:20:40: Dropped Player1 from server (Disconnect by user.)";
+        assert_eq!(parser.parse_line(line).unwrap(), disconnected);
+    }
+
+    #[test]
+    fn test_parse_vote_lines() {
+        let parser = LogLineParser::default();
+        let when = Local.with_ymd_and_hms(2024, 5, 6, 17, 12, 30).unwrap();
+
+        let line = "05/06/2024 - 17:12:30: Player1 called a vote to kick Player2";
+        assert_eq!(
+            parser.parse_line(line).unwrap(),
+            LogLine::VoteStarted {
+                when,
+                caller: "Player1".to_string(),
+                target: "Player2".to_string(),
+            }
+        );
+
+        let line = "05/06/2024 - 17:12:30: Player 3 voted No";
+        assert_eq!(
+            parser.parse_line(line).unwrap(),
+            LogLine::VoteCast {
+                when,
+                name: "Player 3".to_string(),
+                yes: false,
+            }
+        );
+
+        let line = "05/06/2024 - 17:12:30: Vote passed.";
+        assert_eq!(
+            parser.parse_line(line).unwrap(),
+            LogLine::VoteEnded {
+                when,
+                passed: true,
+                reason: String::new(),
+            }
+        );
+
+        let line = "05/06/2024 - 17:12:30: Vote failed: Not enough players voted.";
+        assert_eq!(
+            parser.parse_line(line).unwrap(),
+            LogLine::VoteEnded {
+                when,
+                passed: false,
+                reason: "Not enough players voted.".to_string(),
+            }
+        );
+
+        let line = "05/06/2024 - 17:12:30: You cannot call a vote yet.";
+        assert_eq!(
+            parser.parse_line(line).unwrap(),
+            LogLine::VoteCooldown {
+                when,
+                seconds: None
+            }
+        );
+
+        let line = "05/06/2024 - 17:12:30: You cannot call a new vote for 58 seconds.";
+        assert_eq!(
+            parser.parse_line(line).unwrap(),
+            LogLine::VoteCooldown {
+                when,
+                seconds: Some(58)
+            }
+        );
+
+        // Chat can't fake a vote
+        let line = "05/06/2024 - 17:12:30: Player1 :  Vote passed.";
+        assert!(matches!(
+            parser.parse_line(line),
+            Some(LogLine::Chat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_objective_line() {
+        let parser = LogLineParser::default();
+
+        let when = Local.with_ymd_and_hms(2024, 5, 6, 17, 5, 58).unwrap();
+        let line = "05/06/2024 - 17:05:58: Benito Tortellini (real), Slightly Destructive Justice, TopG_14 captured Third Capture point for team #3";
+        let result = parser.parse_line(line).unwrap();
+        assert_eq!(
+            result,
+            LogLine::Capture {
+                when,
+                cappers: "Benito Tortellini (real), Slightly Destructive Justice, TopG_14"
+                    .to_string(),
+                point: "Third Capture point".to_string(),
+                team: 3,
+            }
+        );
+
+        let line = "05/06/2024 - 17:05:58: Player1 defended Second Capture point for team #2";
+        let result = parser.parse_line(line).unwrap();
+        assert_eq!(
+            result,
+            LogLine::Defended {
+                when,
+                name: "Player1".to_string(),
+                point: "Second Capture point".to_string(),
+                team: 2,
+            }
+        );
+
+        // Chat that looks like a capture is chat
+        let line = "05/06/2024 - 17:05:58: Player1 :  I captured Second Capture point for team #2";
+        let result = parser.parse_line(line).unwrap();
+        assert!(matches!(result, LogLine::Chat { .. }));
+    }
+
+    #[test]
+    fn test_parse_item_line() {
+        let parser = LogLineParser::default();
+
+        let when = Local.with_ymd_and_hms(2024, 5, 6, 17, 14, 20).unwrap();
+        let line = "05/06/2024 - 17:14:20: Player1 has found: Strange Scattergun";
+        assert_eq!(
+            parser.parse_line(line),
+            Some(LogLine::ItemFound {
+                when,
+                name: "Player1".to_string(),
+                item: "Strange Scattergun".to_string(),
+            })
+        );
+
+        let line = "05/06/2024 - 17:14:20: Player 2 has crafted: Refined Metal";
+        assert_eq!(
+            parser.parse_line(line),
+            Some(LogLine::ItemFound {
+                when,
+                name: "Player 2".to_string(),
+                item: "Refined Metal".to_string(),
+            })
+        );
+
+        let line = "05/06/2024 - 17:14:20: Player1 has earned the achievement Krazy Ivan";
+        assert_eq!(
+            parser.parse_line(line),
+            Some(LogLine::Achievement {
+                when,
+                name: "Player1".to_string(),
+                achievement: "Krazy Ivan".to_string(),
+            })
+        );
+
+        // Chat can't fake a drop
+        let line = "05/06/2024 - 17:14:20: Player1 :  Player2 has found: Unusual Hat";
+        assert!(matches!(
+            parser.parse_line(line),
+            Some(LogLine::Chat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_server_message_line() {
+        let parser = LogLineParser::default();
+
+        let when = Local.with_ymd_and_hms(2024, 5, 6, 17, 14, 20).unwrap();
+        let line = "05/06/2024 - 17:14:20: [SM] Next map: pl_upward";
+        assert_eq!(
+            parser.parse_line(line),
+            Some(LogLine::ServerMessage {
+                when,
+                message: "[SM] Next map: pl_upward".to_string(),
+            })
+        );
+
+        // Admin chat can't be told from a player named "[SM] Admin", so it's chat
+        let line = "05/06/2024 - 17:14:20: [SM] Admin :  Be nice";
+        assert!(matches!(
+            parser.parse_line(line),
+            Some(LogLine::Chat { .. })
+        ));
+
+        // Plugin messages that look like other lines are still from the server
+        for line in [
+            "05/06/2024 - 17:14:20: [SM] Player1 connected",
+            "05/06/2024 - 17:14:20: [SM] Player1 called a vote to kick Player2",
+            "05/06/2024 - 17:14:20: [SM] Player1 has found: Strange Scattergun",
+        ] {
+            assert!(
+                matches!(parser.parse_line(line), Some(LogLine::ServerMessage { .. })),
+                "{}",
+                line
+            );
+        }
+    }
+
     #[test]
     fn test_parse_suicided_line() {
         let parser = LogLineParser::default();
@@ -334,7 +1204,27 @@ mod tests {
                 when,
                 id: 1371,
                 name: "Player1".to_string(),
-                steam_id32: "[U:1:169802]".to_string()
+                steam_id32: "[U:1:169802]".to_string(),
+                connected: Duration::seconds(10 * 60 + 3),
+                ping: 85,
+                loss: 0,
+                state: "active".to_string(),
+            }
+        );
+
+        let line = r#"05/08/2024 - 13:30:42: #   1372 "Bot"               [U:1:1234567]  1:02:03        5   12 spawning"#;
+        let result = parser.parse_line(line).unwrap();
+        assert_eq!(
+            result,
+            LogLine::StatusForPlayer {
+                when,
+                id: 1372,
+                name: "Bot".to_string(),
+                steam_id32: "[U:1:1234567]".to_string(),
+                connected: Duration::seconds(3600 + 2 * 60 + 3),
+                ping: 5,
+                loss: 12,
+                state: "spawning".to_string(),
             }
         );
     }
@@ -429,6 +1319,80 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_server_info() {
+        let parser = LogLineParser::default();
+        let when = Local.with_ymd_and_hms(2024, 5, 6, 17, 5, 50).unwrap();
+        let info = |line: &str| match parser.parse_line(line) {
+            Some(LogLine::ServerInfo { when: w, info }) if w == when => Some(info),
+            _ => None,
+        };
+
+        assert_eq!(
+            info("05/06/2024 - 17:05:50: hostname: Valve Matchmaking Server (Frankfurt srcds101-fra2 #70)"),
+            Some(ServerInfoLine::Hostname(
+                "Valve Matchmaking Server (Frankfurt srcds101-fra2 #70)".to_string()
+            ))
+        );
+        assert_eq!(
+            info("05/06/2024 - 17:05:50: version : 8835751/24 8835751 secure"),
+            Some(ServerInfoLine::Version(
+                "8835751/24 8835751 secure".to_string()
+            ))
+        );
+        assert_eq!(
+            info("05/06/2024 - 17:05:50: udp/ip  : 169.254.171.56:28664"),
+            Some(ServerInfoLine::UdpIp("169.254.171.56:28664".to_string()))
+        );
+        assert_eq!(
+            info("05/06/2024 - 17:05:50: steamid : [A:1:509071377:29317] (90197908612698129)"),
+            Some(ServerInfoLine::SteamId("[A:1:509071377:29317]".to_string()))
+        );
+        assert_eq!(
+            info("05/06/2024 - 17:05:50: map     : pl_badwater at: 0 x, 0 y, 0 z"),
+            Some(ServerInfoLine::Map("pl_badwater".to_string()))
+        );
+        assert_eq!(
+            info("05/06/2024 - 17:05:50: tags    : hidden,increased_maxplayers,payload,valve"),
+            Some(ServerInfoLine::Tags(vec![
+                "hidden".to_string(),
+                "increased_maxplayers".to_string(),
+                "payload".to_string(),
+                "valve".to_string()
+            ]))
+        );
+        assert_eq!(
+            info("05/06/2024 - 17:05:50: players : 24 humans, 0 bots (32 max)"),
+            Some(ServerInfoLine::Players {
+                humans: 24,
+                bots: 0,
+                max: 32
+            })
+        );
+
+        // Not used
+        assert_eq!(
+            parser.parse_line("05/06/2024 - 17:05:50: edicts  : 959 used of 2048 max"),
+            None
+        );
+
+        // A player named map is still chatting
+        assert!(matches!(
+            parser.parse_line("05/06/2024 - 17:05:50: map :  gg"),
+            Some(LogLine::Chat { .. })
+        ));
+
+        // Even when the chat fits the padding of the info line
+        assert!(matches!(
+            parser.parse_line("05/06/2024 - 17:05:50: version :  hi"),
+            Some(LogLine::Chat { .. })
+        ));
+        assert!(matches!(
+            parser.parse_line("05/06/2024 - 17:05:50: steamid :  hi"),
+            Some(LogLine::Chat { .. })
+        ));
+    }
+
     #[test]
     fn test_parse_tf_lobby_player_header() {
         let parser = LogLineParser::default();
@@ -469,6 +1433,14 @@ mod tests {
             }
         );
 
+        let line = r#"05/08/2024 - 13:30:42: Player1 :  I killed you with skill."#;
+        let result = parser.parse_line(line).unwrap();
+        assert!(matches!(result, LogLine::Chat { .. }));
+
+        let line = r#"05/08/2024 - 13:30:42: Player1 :  I changed name to Player2"#;
+        let result = parser.parse_line(line).unwrap();
+        assert!(matches!(result, LogLine::Chat { .. }));
+
         let line = r#"05/08/2024 - 13:30:42: Player1 :  suicided."#;
         let result = parser.parse_line(line).unwrap();
         assert_eq!(
@@ -0,0 +1,133 @@
+use std::io;
+use std::path::Path;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread::sleep;
+use std::time::Duration;
+
+/// Wakes the logfile watcher when the log file changes.
+/// On Linux this uses inotify. Elsewhere, or if inotify can't be used,
+/// the watcher falls back to polling.
+pub struct LogfileNotifier {
+    changes: Option<Receiver<()>>,
+}
+
+impl LogfileNotifier {
+    pub fn new(filename: &str) -> Self {
+        let changes = match watch(Path::new(filename)) {
+            Ok(changes) => Some(changes),
+            Err(error) => {
+                log::warn!(
+                    "Can't watch {} for changes, polling it instead: {}",
+                    filename,
+                    error
+                );
+                None
+            }
+        };
+
+        Self { changes }
+    }
+
+    /// Waits until the log file changes, or for the timeout at most
+    pub fn wait(&mut self, timeout: Duration) {
+        let Some(changes) = &self.changes else {
+            sleep(timeout);
+            return;
+        };
+
+        match changes.recv_timeout(timeout) {
+            // Many writes can come in at once, one read is enough for all of them
+            Ok(()) => while changes.try_recv().is_ok() {},
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                log::warn!("Stopped getting log file changes, polling it instead");
+                self.changes = None;
+            }
+        }
+    }
+}
+
+/// Watches the directory rather than the file, so it works when the file is
+/// created later or replaced by -conclearlog
+#[cfg(target_os = "linux")]
+fn watch(path: &Path) -> io::Result<Receiver<()>> {
+    use inotify::{Inotify, WatchMask};
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No file name"))?
+        .to_os_string();
+
+    let mut inotify = Inotify::init()?;
+    inotify.watches().add(
+        dir,
+        WatchMask::MODIFY | WatchMask::CREATE | WatchMask::MOVED_TO,
+    )?;
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        loop {
+            let events = match inotify.read_events_blocking(&mut buffer) {
+                Ok(events) => events,
+                Err(error) => {
+                    log::error!("Error reading inotify events: {}", error);
+                    return;
+                }
+            };
+
+            let changed = events
+                .into_iter()
+                .any(|event| event.name == Some(name.as_os_str()));
+            if changed && sender.send(()).is_err() {
+                return;
+            }
+        }
+    });
+
+    Ok(receiver)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn watch(_path: &Path) -> io::Result<Receiver<()>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "File change notifications are only used on Linux",
+    ))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test_wakes_on_change() {
+        let dir = std::env::temp_dir().join("tf2monitor_test_notifier");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let filename = dir.join("console.log");
+
+        let mut notifier = LogfileNotifier::new(filename.to_str().unwrap());
+        assert!(notifier.changes.is_some());
+
+        let writer = std::thread::spawn({
+            let filename = filename.clone();
+            move || {
+                sleep(Duration::from_millis(100));
+                std::fs::write(filename, "05/06/2024 - 17:02:55: Player1 connected\n").unwrap();
+            }
+        });
+
+        let start = Instant::now();
+        notifier.wait(Duration::from_secs(10));
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        writer.join().unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
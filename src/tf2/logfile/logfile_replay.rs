@@ -0,0 +1,274 @@
+// Replays a saved console.log through the app, without TF2 or RCON:
+//
+//     tf2monitor --replay path/to/console.log --speed 10x
+//
+// The lines are paced by their con_timestamp prefixes and sent on the logfile bus,
+// as if the logfile watcher had read them. With --headless there's no GUI, the
+// scoreboard is printed to stdout whenever it changes.
+
+use super::line_parser::LogLineParser;
+use crate::appbus::AppBus;
+use crate::tf2::lobby::Lobby;
+use crate::utils::BoxResult;
+use chrono::prelude::*;
+use fs_err as fs;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
+
+pub const USAGE: &str =
+    "Usage: tf2monitor [--replay <path to console.log> [--speed <speed, like 10x>] [--headless]]";
+
+/// Long pauses in the log, like between matches, are cut to this
+const MAX_PAUSE: Duration = Duration::from_secs(10);
+
+/// Headless replays end when the lobby hasn't changed for this long after the last line
+const HEADLESS_IDLE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayOptions {
+    pub filename: String,
+
+    /// 1.0 is real time
+    pub speed: f64,
+    pub headless: bool,
+}
+
+impl ReplayOptions {
+    /// Reads the command line arguments, without the program name.
+    /// Returns None without --replay, and the app runs as usual.
+    pub fn from_args(args: &[String]) -> Result<Option<Self>, String> {
+        let mut filename = None;
+        let mut speed = 1.0;
+        let mut headless = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--replay" => {
+                    let value = args.next().ok_or("--replay needs a file name")?;
+                    filename = Some(value.clone());
+                }
+                "--speed" => {
+                    let value = args.next().ok_or("--speed needs a value, like 10x")?;
+                    speed = parse_speed(value)?;
+                }
+                "--headless" => headless = true,
+                _ => return Err(format!("Unknown argument '{}'", arg)),
+            }
+        }
+
+        match filename {
+            Some(filename) => Ok(Some(Self {
+                filename,
+                speed,
+                headless,
+            })),
+            None if speed != 1.0 || headless => {
+                Err("--speed and --headless are only used with --replay".to_string())
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// "10x", "10" and "0.5x" are all fine
+fn parse_speed(value: &str) -> Result<f64, String> {
+    match value.trim_end_matches(['x', 'X']).parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(speed),
+        _ => Err(format!(
+            "Speed '{}' is not valid, use something like 10x",
+            value
+        )),
+    }
+}
+
+/// Start the replay thread, it stops at the end of the log
+pub fn start(options: &ReplayOptions, bus: &Arc<Mutex<AppBus>>) -> thread::JoinHandle<()> {
+    let replay = LogfileReplay {
+        options: options.clone(),
+        bus: Arc::clone(bus),
+    };
+
+    thread::spawn(move || match replay.run() {
+        Ok(count) => log::info!(
+            "Replay of {} done, {} lines",
+            replay.options.filename,
+            count
+        ),
+        Err(error) => log::error!("Could not replay {}: {}", replay.options.filename, error),
+    })
+}
+
+pub struct LogfileReplay {
+    options: ReplayOptions,
+    bus: Arc<Mutex<AppBus>>,
+}
+
+impl LogfileReplay {
+    /// Sends the lines of the log on the bus, paced by their timestamps.
+    /// Returns the number of lines sent.
+    pub fn run(&self) -> BoxResult<usize> {
+        let data = fs::read(&self.options.filename)?;
+        let text = String::from_utf8_lossy(&data);
+        log::info!(
+            "Replaying {} at {}x",
+            self.options.filename,
+            self.options.speed
+        );
+
+        let mut parser = LogLineParser::default();
+        parser.scan_date_order(&text);
+        let start = Instant::now();
+        let mut replay_time = Duration::ZERO;
+        let mut last_when: Option<DateTime<Local>> = None;
+        let mut count = 0;
+
+        for line in text.lines() {
+            if let Some(when) = parser.try_get_when(line) {
+                if let Some(last_when) = last_when {
+                    // Lines out of order don't go back in time
+                    let pause = (when - last_when).to_std().unwrap_or_default();
+                    replay_time += pause.min(MAX_PAUSE).div_f64(self.options.speed);
+                }
+                last_when = Some(when);
+
+                if let Some(wait) = replay_time.checked_sub(start.elapsed()) {
+                    sleep(wait);
+                }
+            }
+
+            if let Some(logline) = parser.parse_line(line) {
+                parser.update_roster(&logline);
+                AppBus::send_logline(&self.bus, logline);
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+/// Prints the scoreboard whenever it changes, until the replay is done
+pub fn run_headless(bus: &Arc<Mutex<AppBus>>, replay: thread::JoinHandle<()>) {
+    let mut reports = bus.lock().unwrap().lobby_report_bus.add_rx();
+    let mut last_summary = String::new();
+
+    loop {
+        match reports.recv_timeout(HEADLESS_IDLE) {
+            Ok(lobby) => {
+                let summary = lobby_summary(&lobby);
+                if summary != last_summary {
+                    println!("{}\n", summary);
+                    last_summary = summary;
+                }
+            }
+            Err(_) if replay.is_finished() => break,
+            Err(_) => {}
+        }
+    }
+}
+
+fn lobby_summary(lobby: &Lobby) -> String {
+    let map = if lobby.server.map.is_empty() {
+        "Unknown map"
+    } else {
+        lobby.server.map.as_str()
+    };
+
+    let mut players: Vec<_> = lobby.players.iter().collect();
+    players.sort_by(|a, b| b.kills.cmp(&a.kills).then(a.name.cmp(&b.name)));
+
+    let mut lines = vec![format!("{}, {} players", map, players.len())];
+    for player in players {
+        lines.push(format!(
+            "  {:<32} {:<8} {:>3} kills {:>3} deaths",
+            player.name,
+            format!("{:?}", player.team),
+            player.kills,
+            player.deaths
+        ));
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tf2::logfile::LogLine;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_from_args() {
+        assert_eq!(ReplayOptions::from_args(&[]), Ok(None));
+
+        let options =
+            ReplayOptions::from_args(&args(&["--replay", "console.log", "--speed", "10x"]));
+        assert_eq!(
+            options,
+            Ok(Some(ReplayOptions {
+                filename: "console.log".to_string(),
+                speed: 10.0,
+                headless: false,
+            }))
+        );
+
+        let options = ReplayOptions::from_args(&args(&["--headless", "--replay", "a.log"]));
+        assert_eq!(options.unwrap().unwrap().speed, 1.0);
+
+        assert!(ReplayOptions::from_args(&args(&["--replay"])).is_err());
+        assert!(ReplayOptions::from_args(&args(&["--replay", "a.log", "--speed", "0x"])).is_err());
+        assert!(ReplayOptions::from_args(&args(&["--speed", "2x"])).is_err());
+        assert!(ReplayOptions::from_args(&args(&["--verbose"])).is_err());
+    }
+
+    #[test]
+    fn test_replay_is_paced() {
+        let filename = std::env::temp_dir().join("tf2monitor_test_replay.log");
+        std::fs::write(
+            &filename,
+            "05/06/2024 - 17:00:00: Player1 connected\n\
+             Some line without a timestamp\n\
+             05/06/2024 - 17:00:02: Player1 killed Player2 with scattergun.\n\
+             05/06/2024 - 20:00:00: Player1 :  back after a long break\n",
+        )
+        .unwrap();
+
+        let bus = Arc::new(Mutex::new(AppBus::new()));
+        let mut logfile_bus_rx = bus.lock().unwrap().logfile_bus.add_rx();
+        let replay = LogfileReplay {
+            options: ReplayOptions {
+                filename: filename.to_str().unwrap().to_string(),
+                speed: 20.0,
+                headless: true,
+            },
+            bus: Arc::clone(&bus),
+        };
+
+        // 2 seconds, and the long break cut to MAX_PAUSE, at 20x
+        let start = Instant::now();
+        assert_eq!(replay.run().unwrap(), 3);
+        let elapsed = start.elapsed();
+        let _ = std::fs::remove_file(&filename);
+
+        assert!(elapsed >= Duration::from_millis(600), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(5), "{:?}", elapsed);
+
+        assert!(matches!(
+            logfile_bus_rx.try_recv(),
+            Ok(LogLine::Connected { .. })
+        ));
+        assert!(matches!(
+            logfile_bus_rx.try_recv(),
+            Ok(LogLine::Kill { .. })
+        ));
+        assert!(matches!(
+            logfile_bus_rx.try_recv(),
+            Ok(LogLine::Chat { .. })
+        ));
+    }
+}
@@ -1,4 +1,5 @@
 use super::line_parser::LogLineParser;
+use super::logfile_notifier::LogfileNotifier;
 use crate::appbus::AppBus;
 use crate::models::app_settings::AppSettings;
 use crate::utils::BoxResult;
@@ -11,18 +12,14 @@ use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Duration;
 use std::{thread, time};
-use thread::sleep;
 
-/// The delay between loops in run()
+/// The longest delay between reads in run(). Reads happen as soon as the file
+/// changes when we get notified about it, otherwise this is the polling delay.
 const LOOP_DELAY: Duration = time::Duration::from_millis(2000);
 
 /// Start the logfile watcher thread to run in the background
 pub fn start(settings: &AppSettings, bus: &Arc<Mutex<AppBus>>) -> thread::JoinHandle<()> {
-    let mut watcher = LogfileWatcher {
-        filename: settings.log_filename.clone(),
-        last_pos: 0,
-        bus: Arc::clone(bus),
-    };
+    let mut watcher = LogfileWatcher::new(&settings.log_filename, bus);
 
     // remove_log_file(&watcher.filename);
 
@@ -46,44 +43,62 @@ pub struct LogfileWatcher {
     pub filename: String,
     pub last_pos: u64,
 
+    /// Bytes read after the last newline. TF2 may be in the middle of writing
+    /// the line, and it may end in the middle of a UTF-8 character.
+    partial_line: Vec<u8>,
+
     bus: Arc<Mutex<AppBus>>,
 }
 
 impl LogfileWatcher {
+    pub fn new(filename: &str, bus: &Arc<Mutex<AppBus>>) -> Self {
+        Self {
+            filename: filename.to_string(),
+            last_pos: 0,
+            partial_line: Vec::new(),
+            bus: Arc::clone(bus),
+        }
+    }
+
     pub fn run(&mut self) {
-        let parser = LogLineParser::default();
+        let mut parser = LogLineParser::default();
         log::info!(
             "Logfile watcher started. Will monitor file: {}",
             self.filename
         );
 
+        let mut notifier = LogfileNotifier::new(&self.filename);
+
         loop {
-            sleep(LOOP_DELAY);
+            self.process_new_data(&mut parser);
 
-            self.process_new_data(&parser);
+            notifier.wait(LOOP_DELAY);
         }
     }
 
-    pub fn process_new_data(&mut self, parser: &LogLineParser) {
+    pub fn process_new_data(&mut self, parser: &mut LogLineParser) {
         // log::info!("Processing new data");
         let new_data = self.read_new_data();
         if let Ok(new_data) = new_data {
-            let mut bus = self.bus.lock().unwrap();
+            parser.scan_date_order(&new_data);
             let lines: Vec<&str> = new_data.lines().collect();
             log::debug!("Got {} new lines in the logfile", lines.len());
             for line in lines {
                 let msg = parser.parse_line(line);
                 if let Some(msg) = msg {
-                    bus.send_logline(msg);
+                    parser.update_roster(&msg);
+                    AppBus::send_logline(&self.bus, msg);
                 }
             }
         } else {
             // log::debug!("Error reading new data. Error: {:?}", new_data.err());
             log::error!("Error reading new data. Resetting position to zero.");
             self.last_pos = 0;
+            self.partial_line.clear();
         }
     }
 
+    /// Returns the complete lines added to the file since the last read
     fn read_new_data(&mut self) -> BoxResult<String> {
         let mut file = fs::File::open(self.filename.as_str())?;
 
@@ -96,6 +111,7 @@ impl LogfileWatcher {
         // File was truncated, start from beginning
         if new_pos < self.last_pos {
             self.last_pos = 0;
+            self.partial_line.clear();
         }
 
         // Seek to the position we last read from
@@ -106,10 +122,116 @@ impl LogfileWatcher {
         let mut buf: Vec<u8> = vec![0; len];
         file.read_exact(&mut buf)?;
 
-        let s = String::from_utf8_lossy(&buf).to_string();
-
         self.last_pos += len as u64;
 
-        Ok(s)
+        // Keep everything after the last newline for the next read. A newline is
+        // never part of a multibyte character, so the complete lines are valid UTF-8.
+        self.partial_line.extend_from_slice(&buf);
+        let complete = match self.partial_line.iter().rposition(|b| *b == b'\n') {
+            Some(newline) => {
+                let rest = self.partial_line.split_off(newline + 1);
+                std::mem::replace(&mut self.partial_line, rest)
+            }
+            None => return Ok("".to_string()),
+        };
+
+        Ok(String::from_utf8_lossy(&complete).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn temp_logfile(name: &str) -> String {
+        let filename = std::env::temp_dir().join(format!("tf2monitor_test_{}.log", name));
+        let _ = std::fs::remove_file(&filename);
+        std::fs::File::create(&filename).unwrap();
+        filename.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_byte_by_byte_appends() {
+        let filename = temp_logfile("byte_by_byte");
+        let bus = Arc::new(Mutex::new(AppBus::new()));
+        let mut watcher = LogfileWatcher::new(&filename, &bus);
+
+        let text = "05/06/2024 - 17:02:55: 𝖁𝖆𝖘𝖎𝖑𝖎𝖘 killed Ünïcödé with scattergun.\n\
+                    05/06/2024 - 17:02:56: Player1 :  gg ✌\n";
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&filename)
+            .unwrap();
+        let mut read = String::new();
+        for byte in text.as_bytes() {
+            file.write_all(&[*byte]).unwrap();
+            file.flush().unwrap();
+
+            let new_data = watcher.read_new_data().unwrap();
+            assert!(new_data.is_empty() || new_data.ends_with('\n'));
+            read.push_str(&new_data);
+        }
+        let _ = std::fs::remove_file(&filename);
+
+        assert_eq!(read, text);
+        assert!(watcher.partial_line.is_empty());
+    }
+
+    #[test]
+    fn test_partial_line_waits_for_newline() {
+        let filename = temp_logfile("partial_line");
+        let bus = Arc::new(Mutex::new(AppBus::new()));
+        let mut watcher = LogfileWatcher::new(&filename, &bus);
+
+        // Cut in the middle of the "é"
+        let line = "05/06/2024 - 17:10:02: Player1 changed name to José\n".as_bytes();
+        let cut = line.len() - 2;
+
+        std::fs::write(&filename, &line[..cut]).unwrap();
+        assert_eq!(watcher.read_new_data().unwrap(), "");
+
+        std::fs::write(&filename, line).unwrap();
+        let new_data = watcher.read_new_data().unwrap();
+
+        // The truncated file starts over, and drops what was buffered
+        std::fs::write(&filename, "05/06/2024").unwrap();
+        assert_eq!(watcher.read_new_data().unwrap(), "");
+        assert_eq!(watcher.partial_line, b"05/06/2024");
+        let _ = std::fs::remove_file(&filename);
+
+        assert_eq!(new_data.as_bytes(), line);
+        let parsed = LogLineParser::default().parse_line(new_data.trim_end());
+        assert!(matches!(
+            parsed,
+            Some(crate::tf2::logfile::LogLine::NameChange { new_name, .. }) if new_name == "José"
+        ));
+    }
+
+    #[test]
+    fn test_full_bus_doesnt_hold_the_lock() {
+        let filename = temp_logfile("full_bus");
+        let lines: String = (0..20)
+            .map(|i| format!("05/06/2024 - 17:01:12: Player{} connected\n", i))
+            .collect();
+        std::fs::write(&filename, lines).unwrap();
+
+        let bus = Arc::new(Mutex::new(AppBus::new()));
+        bus.lock().unwrap().logfile_bus = bus::Bus::new(2);
+        let mut reader = bus.lock().unwrap().logfile_bus.add_rx();
+
+        let mut watcher = LogfileWatcher::new(&filename, &bus);
+        let watcher = thread::spawn(move || {
+            watcher.process_new_data(&mut LogLineParser::default());
+        });
+
+        // Like the lobby thread, take the lock between reads
+        for _ in 0..20 {
+            drop(bus.lock().unwrap());
+            assert!(reader.recv_timeout(Duration::from_secs(5)).is_ok());
+        }
+        watcher.join().unwrap();
+        let _ = std::fs::remove_file(&filename);
     }
 }
@@ -1,7 +1,9 @@
 pub mod line_parser;
+pub mod logfile_notifier;
+pub mod logfile_replay;
 pub mod logfile_watcher;
 
-use chrono::prelude::*;
+use chrono::{prelude::*, Duration};
 
 ///  This type covers the different types of log lines that can be parsed from the log file.
 /// It's unprocessed data mostly, so it's names instead of SteamIDs.
@@ -30,6 +32,18 @@ pub enum LogLine {
 
         /// The player's SteamID32 as a string.
         steam_id32: String,
+
+        /// How long the player has been connected to the server
+        connected: Duration,
+
+        /// Ping in milliseconds
+        ping: u32,
+
+        /// Packet loss in percent
+        loss: u32,
+
+        /// "spawning" or "active"
+        state: String,
     },
 
     /// The output of tf_lobby_debug command
@@ -48,23 +62,159 @@ pub enum LogLine {
         crit: bool,
     },
 
+    /// A kill line that can be read in more than one way, because of names
+    /// with " killed " or " with " in them. See LogLineParser::parse_killed_line.
+    AmbiguousKill {
+        /// Local time
+        when: DateTime<Local>,
+
+        /// All the ways to read the line
+        splits: Vec<KillSplit>,
+        crit: bool,
+    },
+
+    /// A player connected to the server.
+    /// Example:
+    /// 05/06/2024 - 17:01:12: Player1 connected
+    Connected {
+        /// Local time
+        when: DateTime<Local>,
+        name: String,
+    },
+
+    /// A player left the server.
+    /// Examples:
+    /// 05/06/2024 - 17:20:40: Player1 left the game (Disconnect by user.)
+    /// 05/06/2024 - 17:20:40: Dropped Player1 from server (Disconnect by user.)
+    Disconnected {
+        /// Local time
+        when: DateTime<Local>,
+        name: String,
+        reason: String,
+    },
+
+    /// A player changed their name.
+    /// Example:
+    /// 05/06/2024 - 17:10:02: Player1 changed name to Player2
+    NameChange {
+        /// Local time
+        when: DateTime<Local>,
+        old_name: String,
+        new_name: String,
+    },
+
     Suicide {
         /// Local time
         when: DateTime<Local>,
         name: String,
     },
-    /*
-    05/06/2024 - 17:05:50: hostname: Valve Matchmaking Server (Frankfurt srcds101-fra2 #70)
-    05/06/2024 - 17:05:50: version : 8835751/24 8835751 secure
-    05/06/2024 - 17:05:50: udp/ip  : 169.254.171.56:28664
-    05/06/2024 - 17:05:50: steamid : [A:1:509071377:29317] (90197908612698129)
-    05/06/2024 - 17:05:50: account : not logged in  (No account specified)
-    05/06/2024 - 17:05:50: map     : pl_badwater at: 0 x, 0 y, 0 z
-    05/06/2024 - 17:05:50: tags    : hidden,increased_maxplayers,payload,valve
-    05/06/2024 - 17:05:50: players : 24 humans, 0 bots (32 max)
-    05/06/2024 - 17:05:50: edicts  : 959 used of 2048 max
-         */
-    // 05/06/2024 - 17:05:58: Benito Tortellini (real), Slightly Destructive Justice, TopG_14 captured Third Capture point for team #3
+    /// One of the server info lines at the start of the status output.
+    /// Example:
+    /// 05/06/2024 - 17:05:50: map     : pl_badwater at: 0 x, 0 y, 0 z
+    ServerInfo {
+        /// Local time
+        when: DateTime<Local>,
+        info: ServerInfoLine,
+    },
+
+    /// One or more players captured a control point.
+    /// The names are separated by ", ", but names can contain commas too, so
+    /// they are split against the players in the lobby.
+    /// Example:
+    /// 05/06/2024 - 17:05:58: Benito Tortellini (real), Slightly Destructive Justice, TopG_14 captured Third Capture point for team #3
+    Capture {
+        /// Local time
+        when: DateTime<Local>,
+        cappers: String,
+        point: String,
+
+        /// 2 is RED and 3 is BLU
+        team: u32,
+    },
+
+    /// A player defended a control point or the intelligence.
+    /// Example:
+    /// 05/06/2024 - 17:07:12: Player1 defended Second Capture point for team #2
+    Defended {
+        /// Local time
+        when: DateTime<Local>,
+        name: String,
+        point: String,
+
+        /// 2 is RED and 3 is BLU
+        team: u32,
+    },
+
+    /// A kick vote was called.
+    /// Example:
+    /// 05/06/2024 - 17:12:30: Player1 called a vote to kick Player2
+    VoteStarted {
+        /// Local time
+        when: DateTime<Local>,
+        caller: String,
+        target: String,
+    },
+
+    /// A player voted in the current vote. Only the votes we can see are logged.
+    /// Example:
+    /// 05/06/2024 - 17:12:33: Player3 voted Yes
+    VoteCast {
+        /// Local time
+        when: DateTime<Local>,
+        name: String,
+        yes: bool,
+    },
+
+    /// The current vote ended.
+    /// Examples:
+    /// 05/06/2024 - 17:12:45: Vote passed.
+    /// 05/06/2024 - 17:12:45: Vote failed: Not enough players voted.
+    VoteEnded {
+        /// Local time
+        when: DateTime<Local>,
+        passed: bool,
+        reason: String,
+    },
+
+    /// We tried to call a vote too soon.
+    /// Examples:
+    /// 05/06/2024 - 17:13:02: You cannot call a vote yet.
+    /// 05/06/2024 - 17:13:02: You cannot call a new vote for 58 seconds.
+    VoteCooldown {
+        /// Local time
+        when: DateTime<Local>,
+        seconds: Option<u32>,
+    },
+
+    /// A player got an item. Crafted, traded, unboxed etc items are logged the same way.
+    /// Example:
+    /// 05/06/2024 - 17:14:20: Player1 has found: Strange Scattergun
+    ItemFound {
+        /// Local time
+        when: DateTime<Local>,
+        name: String,
+        item: String,
+    },
+
+    /// A player earned an achievement.
+    /// Example:
+    /// 05/06/2024 - 17:14:25: Player1 has earned the achievement Krazy Ivan
+    Achievement {
+        /// Local time
+        when: DateTime<Local>,
+        name: String,
+        achievement: String,
+    },
+
+    /// A message from a server plugin, not from a player.
+    /// Example:
+    /// 05/06/2024 - 17:14:30: [SM] Next map: pl_upward
+    ServerMessage {
+        /// Local time
+        when: DateTime<Local>,
+        message: String,
+    },
+
     LobbyCreated {
         /// Local time
         when: DateTime<Local>,
@@ -84,3 +234,75 @@ pub enum LogLine {
         team: bool,
     },
 }
+
+impl LogLine {
+    /// The time of the line. The lobby_debug lines and unknown lines have none.
+    pub fn when(&self) -> Option<DateTime<Local>> {
+        match self {
+            LogLine::Unknown { .. } | LogLine::PlayerTeam { .. } => None,
+            LogLine::StatusHeader { when }
+            | LogLine::StatusForPlayer { when, .. }
+            | LogLine::Kill { when, .. }
+            | LogLine::AmbiguousKill { when, .. }
+            | LogLine::Connected { when, .. }
+            | LogLine::Disconnected { when, .. }
+            | LogLine::NameChange { when, .. }
+            | LogLine::Suicide { when, .. }
+            | LogLine::ServerInfo { when, .. }
+            | LogLine::Capture { when, .. }
+            | LogLine::Defended { when, .. }
+            | LogLine::VoteStarted { when, .. }
+            | LogLine::VoteCast { when, .. }
+            | LogLine::VoteEnded { when, .. }
+            | LogLine::VoteCooldown { when, .. }
+            | LogLine::ItemFound { when, .. }
+            | LogLine::Achievement { when, .. }
+            | LogLine::ServerMessage { when, .. }
+            | LogLine::LobbyCreated { when }
+            | LogLine::LobbyDestroyed { when }
+            | LogLine::Chat { when, .. } => Some(*when),
+        }
+    }
+}
+
+/// One way to split a kill line into killer, victim and weapon
+#[derive(Debug, PartialEq, Clone)]
+pub struct KillSplit {
+    pub killer: String,
+    pub victim: String,
+    pub weapon: String,
+}
+
+/// The server info lines printed by the status command, before the players:
+///
+/// 05/06/2024 - 17:05:50: hostname: Valve Matchmaking Server (Frankfurt srcds101-fra2 #70)
+/// 05/06/2024 - 17:05:50: version : 8835751/24 8835751 secure
+/// 05/06/2024 - 17:05:50: udp/ip  : 169.254.171.56:28664
+/// 05/06/2024 - 17:05:50: steamid : [A:1:509071377:29317] (90197908612698129)
+/// 05/06/2024 - 17:05:50: account : not logged in  (No account specified)
+/// 05/06/2024 - 17:05:50: map     : pl_badwater at: 0 x, 0 y, 0 z
+/// 05/06/2024 - 17:05:50: tags    : hidden,increased_maxplayers,payload,valve
+/// 05/06/2024 - 17:05:50: players : 24 humans, 0 bots (32 max)
+/// 05/06/2024 - 17:05:50: edicts  : 959 used of 2048 max
+///
+/// The account and edicts lines are not used.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ServerInfoLine {
+    Hostname(String),
+    Version(String),
+
+    /// IP and port
+    UdpIp(String),
+
+    /// The server's SteamID, like "[A:1:509071377:29317]"
+    SteamId(String),
+
+    /// Map name, without the position
+    Map(String),
+    Tags(Vec<String>),
+    Players {
+        humans: u32,
+        bots: u32,
+        max: u32,
+    },
+}
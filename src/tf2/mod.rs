@@ -1,20 +1,66 @@
 pub mod lobby;
 pub mod logfile;
+pub mod playerlists;
 pub mod rcon;
+pub mod rules;
 pub mod steam;
 
-use crate::{appbus::AppBus, models::app_settings::AppSettings};
+use crate::{
+    appbus::AppBus,
+    models::{app_settings::AppSettings, player_db::PlayerDb},
+};
+use lobby::lobby_thread::LobbyThread;
+use logfile::logfile_replay::ReplayOptions;
+use rules::RuleEngine;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
 
 /// Start the background threads for the TF2 module
 pub fn start(settings: &AppSettings, bus: &Arc<Mutex<AppBus>>) {
     let rcon_thread_handle = rcon::rcon_thread::start(settings, bus);
     let lobby_thread_handle = lobby::lobby_thread::start(settings, bus);
+    let steam_thread_handle = steam::steam_thread::start(settings, bus);
+    let playerlists_thread_handle = playerlists::playerlists_thread::start(settings, bus);
 
     let logfile_watcher_thread_handle = logfile::logfile_watcher::start(settings, bus);
 
     let mut bus = bus.lock().unwrap();
     bus.rcon_thread_handle = Some(rcon_thread_handle);
     bus.lobby_thread_handle = Some(lobby_thread_handle);
+    bus.steam_thread_handle = Some(steam_thread_handle);
     bus.logfile_watcher_thread_handle = Some(logfile_watcher_thread_handle);
+    bus.playerlists_thread_handle = Some(playerlists_thread_handle);
+}
+
+/// Start the background threads for replaying a saved log. There's no RCON
+/// and no logfile watcher, the replay thread takes the watcher's place.
+/// There's no Steam or playerlists thread either, the subscribed playerlists are
+/// read from their cache, and the player database is never saved. So a replay
+/// doesn't go online and doesn't write players.json or the playerlist cache.
+/// Returns the replay thread, it stops at the end of the log.
+pub fn start_replay(
+    settings: &AppSettings,
+    bus: &Arc<Mutex<AppBus>>,
+    options: &ReplayOptions,
+) -> JoinHandle<()> {
+    let mut lobby_thread = LobbyThread::new(
+        settings,
+        bus,
+        PlayerDb::load_read_only(),
+        RuleEngine::load_or_default(),
+    );
+    lobby_thread.follow_log_time();
+    let cache_dir = Path::new(playerlists::PLAYERLIST_CACHE_DIR);
+    for subscribed in playerlists::load_cached(&settings.playerlist_sources, cache_dir) {
+        lobby_thread.update_subscribed_playerlist(subscribed);
+    }
+    let lobby_thread_handle = thread::spawn(move || lobby_thread.run());
+
+    let replay_thread_handle = logfile::logfile_replay::start(options, bus);
+
+    let mut bus = bus.lock().unwrap();
+    bus.lobby_thread_handle = Some(lobby_thread_handle);
+
+    replay_thread_handle
 }
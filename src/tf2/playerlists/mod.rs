@@ -0,0 +1,354 @@
+// Subscriptions to third party playerlists in TF2 Bot Detector format.
+//
+// Each source is an URL or a local file. URLs are fetched with If-None-Match and
+// If-Modified-Since so unchanged lists are not downloaded again.
+// Every successfully read list is cached on disk, and the cached copy is used
+// when the source can't be reached.
+
+pub mod playerlists_thread;
+
+use crate::{
+    models::{app_settings::PlayerlistSource, tf2bd_playerlist::Tf2bdPlayerlist},
+    utils::BoxResult,
+};
+use fs_err as fs;
+use reqwest::{
+    blocking::Client,
+    header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Stored in the same directory as the settings.json file
+pub const PLAYERLIST_CACHE_DIR: &str = "playerlist_cache";
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A playerlist from one of the subscribed sources, sent to the lobby thread
+#[derive(Debug, Clone)]
+pub struct SubscribedPlayerlist {
+    pub source: String,
+    pub playerlist: Tf2bdPlayerlist,
+}
+
+/// The cached copies of the subscribed lists. Nothing is fetched and the cache
+/// isn't written, for replaying a saved log.
+pub fn load_cached(sources: &[PlayerlistSource], cache_dir: &Path) -> Vec<SubscribedPlayerlist> {
+    sources
+        .iter()
+        .filter_map(|source| {
+            let subscription = PlayerlistSubscription::new(source, cache_dir);
+            match subscription.load_cache() {
+                Ok(playerlist) => Some(SubscribedPlayerlist {
+                    source: source.name.clone(),
+                    playerlist,
+                }),
+                Err(error) => {
+                    log::warn!("No cached copy of playerlist '{}': {}", source.name, error);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Info needed for conditional requests, saved next to the cached list
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+struct CacheMeta {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+pub struct PlayerlistSubscription {
+    pub source: PlayerlistSource,
+    cache_dir: PathBuf,
+    client: Client,
+    meta: CacheMeta,
+    next_refresh: Instant,
+}
+
+impl PlayerlistSubscription {
+    pub fn new(source: &PlayerlistSource, cache_dir: &Path) -> Self {
+        let client = Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        let mut subscription = Self {
+            source: source.clone(),
+            cache_dir: cache_dir.to_path_buf(),
+            client,
+            meta: CacheMeta::default(),
+            next_refresh: Instant::now(),
+        };
+
+        // Only use the etag etc if we still have the list it belongs to
+        if subscription.cache_filename().exists() {
+            if let Ok(json) = fs::read_to_string(subscription.meta_filename()) {
+                subscription.meta = serde_json::from_str(&json).unwrap_or_default();
+            }
+        }
+
+        subscription
+    }
+
+    pub fn is_due(&self) -> bool {
+        Instant::now() >= self.next_refresh
+    }
+
+    /// Reads the list from its source, or from the cache if the source
+    /// is unreachable or hasn't changed.
+    pub fn refresh(&mut self) -> Option<Tf2bdPlayerlist> {
+        let refresh_delay = Duration::from_secs(self.source.refresh_minutes * 60);
+        self.next_refresh = Instant::now() + refresh_delay;
+
+        let result = if self.is_url() {
+            self.fetch_url()
+        } else {
+            self.read_file()
+        };
+
+        match result {
+            Ok(Some(playerlist)) => return Some(playerlist),
+            Ok(None) => {
+                log::debug!("Playerlist '{}' is not modified", self.source.name);
+            }
+            Err(error) => {
+                log::warn!(
+                    "Could not read playerlist '{}' from {}: {}. Using cached copy.",
+                    self.source.name,
+                    self.source.location,
+                    error
+                );
+            }
+        }
+
+        match self.load_cache() {
+            Ok(playerlist) => Some(playerlist),
+            Err(error) => {
+                log::warn!(
+                    "No cached copy of playerlist '{}': {}",
+                    self.source.name,
+                    error
+                );
+                None
+            }
+        }
+    }
+
+    fn is_url(&self) -> bool {
+        let location = &self.source.location;
+        location.starts_with("http://") || location.starts_with("https://")
+    }
+
+    /// Returns None if the server says the list is not modified since last time
+    fn fetch_url(&mut self) -> BoxResult<Option<Tf2bdPlayerlist>> {
+        let mut request = self.client.get(&self.source.location);
+        if let Some(etag) = &self.meta.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &self.meta.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+
+        log::debug!("Requesting: {}", self.source.location);
+        let response = request.send()?;
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+
+        let response = response.error_for_status()?;
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+        let meta = CacheMeta {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        };
+
+        let json = response.text()?;
+        let playerlist: Tf2bdPlayerlist = serde_json::from_str(&json)?;
+
+        self.meta = meta;
+        self.save_cache(&json);
+
+        Ok(Some(playerlist))
+    }
+
+    fn read_file(&mut self) -> BoxResult<Option<Tf2bdPlayerlist>> {
+        let json = fs::read_to_string(&self.source.location)?;
+        let playerlist: Tf2bdPlayerlist = serde_json::from_str(&json)?;
+
+        self.save_cache(&json);
+
+        Ok(Some(playerlist))
+    }
+
+    fn load_cache(&self) -> BoxResult<Tf2bdPlayerlist> {
+        let json = fs::read_to_string(self.cache_filename())?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    fn save_cache(&self, json: &str) {
+        let result = || -> BoxResult<()> {
+            fs::create_dir_all(&self.cache_dir)?;
+            fs::write(self.cache_filename(), json)?;
+            fs::write(
+                self.meta_filename(),
+                serde_json::to_string_pretty(&self.meta)?,
+            )?;
+            Ok(())
+        }();
+
+        if let Err(error) = result {
+            log::error!(
+                "Could not cache playerlist '{}': {}",
+                self.source.name,
+                error
+            );
+        }
+    }
+
+    /// The source name made safe to use as a filename
+    fn cache_basename(&self) -> String {
+        self.source
+            .name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect()
+    }
+
+    fn cache_filename(&self) -> PathBuf {
+        self.cache_dir
+            .join(format!("{}.json", self.cache_basename()))
+    }
+
+    fn meta_filename(&self) -> PathBuf {
+        self.cache_dir
+            .join(format!("{}.meta.json", self.cache_basename()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_server;
+    use std::sync::{Arc, Mutex};
+
+    const SAMPLE_PLAYERLIST: &str = include_str!("../../../testdata/playerlist.sample.json");
+    const ETAG_VALUE: &str = "\"v1\"";
+
+    /// A playerlist host that sends the list with an ETag, and then answers
+    /// 304 Not Modified to the requests after the first
+    fn start_playerlist_host(requests: usize) -> (String, Arc<Mutex<Vec<String>>>) {
+        let mut responses = vec![("200 OK", "ETag: \"v1\"", SAMPLE_PLAYERLIST.to_string())];
+        responses.resize(requests, ("304 Not Modified", "", String::new()));
+
+        let (url, seen) = test_server::start_with_headers(responses);
+        (format!("{}/playerlist.json", url), seen)
+    }
+
+    fn temp_cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tf2monitor_test_{}", name));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn source(location: &str) -> PlayerlistSource {
+        PlayerlistSource {
+            name: "Sample list".to_string(),
+            location: location.to_string(),
+            refresh_minutes: 60,
+        }
+    }
+
+    #[test]
+    fn test_fetch_with_etag_and_cache() {
+        let (url, requests) = start_playerlist_host(2);
+        let cache_dir = temp_cache_dir("playerlist_etag");
+
+        let mut subscription = PlayerlistSubscription::new(&source(&url), &cache_dir);
+        assert!(subscription.is_due());
+        let playerlist = subscription.refresh().unwrap();
+        assert_eq!(playerlist.title(), Some("Sample playerlist"));
+        assert!(!subscription.is_due());
+        assert!(cache_dir.join("Sample_list.json").exists());
+
+        // A new subscription picks up the etag from the cache, and gets a 304
+        let mut subscription = PlayerlistSubscription::new(&source(&url), &cache_dir);
+        let cached = subscription.refresh().unwrap();
+        assert_eq!(cached, playerlist);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(!requests[0].to_lowercase().contains("if-none-match"));
+        assert!(requests[1]
+            .to_lowercase()
+            .contains(&format!("if-none-match: {}", ETAG_VALUE)));
+
+        let _ = std::fs::remove_dir_all(&cache_dir);
+    }
+
+    #[test]
+    fn test_unreachable_url_uses_cache() {
+        let (url, _requests) = start_playerlist_host(1);
+        let cache_dir = temp_cache_dir("playerlist_unreachable");
+
+        let mut subscription = PlayerlistSubscription::new(&source(&url), &cache_dir);
+        let playerlist = subscription.refresh().unwrap();
+
+        // The host has stopped answering, so the cached copy is used
+        let unreachable = "http://127.0.0.1:1/playerlist.json";
+        let mut subscription = PlayerlistSubscription::new(&source(unreachable), &cache_dir);
+        assert_eq!(subscription.refresh(), Some(playerlist));
+
+        let _ = std::fs::remove_dir_all(&cache_dir);
+    }
+
+    #[test]
+    fn test_local_file_with_fallback() {
+        let cache_dir = temp_cache_dir("playerlist_file");
+        std::fs::create_dir_all(&cache_dir).unwrap();
+        let filename = cache_dir.join("local_playerlist.json");
+        std::fs::write(&filename, SAMPLE_PLAYERLIST).unwrap();
+
+        let source = source(filename.to_str().unwrap());
+        let mut subscription = PlayerlistSubscription::new(&source, &cache_dir);
+        let playerlist = subscription.refresh().unwrap();
+        assert_eq!(playerlist.players.len(), 5);
+
+        // File is gone, use the cached copy
+        std::fs::remove_file(&filename).unwrap();
+        assert_eq!(subscription.refresh(), Some(playerlist));
+
+        // Nothing cached and nothing to read
+        let missing = PlayerlistSource {
+            name: "Missing".to_string(),
+            ..source.clone()
+        };
+        let mut other = PlayerlistSubscription::new(&missing, &cache_dir);
+        assert_eq!(other.refresh(), None);
+
+        // A replay only looks in the cache, even for a list it could fetch
+        let unreachable = PlayerlistSource {
+            location: "http://127.0.0.1:1/playerlist.json".to_string(),
+            ..source
+        };
+        let cached = load_cached(&[unreachable, missing], &cache_dir);
+        assert_eq!(cached.len(), 1);
+        assert_eq!(cached[0].source, "Sample list");
+        assert_eq!(Some(&cached[0].playerlist), subscription.refresh().as_ref());
+
+        let _ = std::fs::remove_dir_all(&cache_dir);
+    }
+}
@@ -0,0 +1,72 @@
+use super::{PlayerlistSubscription, SubscribedPlayerlist, PLAYERLIST_CACHE_DIR};
+use crate::{appbus::AppBus, models::app_settings::AppSettings};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    thread::{self, sleep},
+    time::Duration,
+};
+
+/// The delay between loops in run()
+const LOOP_DELAY: Duration = Duration::from_secs(10);
+
+pub struct PlayerlistsThread {
+    bus: Arc<Mutex<AppBus>>,
+    subscriptions: Vec<PlayerlistSubscription>,
+}
+
+/// Start the background thread that keeps the subscribed playerlists up to date
+pub fn start(settings: &AppSettings, bus: &Arc<Mutex<AppBus>>) -> thread::JoinHandle<()> {
+    let mut playerlists_thread = PlayerlistsThread::new(settings, bus);
+
+    thread::spawn(move || playerlists_thread.run())
+}
+
+impl PlayerlistsThread {
+    pub fn new(settings: &AppSettings, bus: &Arc<Mutex<AppBus>>) -> Self {
+        let cache_dir = Path::new(PLAYERLIST_CACHE_DIR);
+        let subscriptions = settings
+            .playerlist_sources
+            .iter()
+            .map(|source| PlayerlistSubscription::new(source, cache_dir))
+            .collect();
+
+        Self {
+            bus: Arc::clone(bus),
+            subscriptions,
+        }
+    }
+
+    pub fn run(&mut self) {
+        log::info!(
+            "Playerlists background thread started with {} sources",
+            self.subscriptions.len()
+        );
+
+        loop {
+            for subscription in self.subscriptions.iter_mut() {
+                if !subscription.is_due() {
+                    continue;
+                }
+
+                if let Some(playerlist) = subscription.refresh() {
+                    log::info!(
+                        "Playerlist '{}' has {} players",
+                        subscription.source.name,
+                        playerlist.players.len()
+                    );
+
+                    AppBus::send_playerlist(
+                        &self.bus,
+                        SubscribedPlayerlist {
+                            source: subscription.source.name.clone(),
+                            playerlist,
+                        },
+                    );
+                }
+            }
+
+            sleep(LOOP_DELAY);
+        }
+    }
+}
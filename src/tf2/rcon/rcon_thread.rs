@@ -101,7 +101,7 @@ impl RconThread {
         for line in lines {
             if let Some((steam_id32, team)) = parse_lobby_debug_line(line) {
                 let logline = LogLine::PlayerTeam { steam_id32, team };
-                self.bus.lock().unwrap().send_logline(logline);
+                AppBus::send_logline(&self.bus, logline);
             }
         }
     }
@@ -0,0 +1,382 @@
+// Rules that flag players automatically when their name or a chat message matches.
+//
+// The rules.json file uses the same layout as the TF2 Bot Detector rules files,
+// so most of those can be used as they are:
+//
+// {
+//   "rules": [
+//     {
+//       "description": "Known bot names",
+//       "triggers": {
+//         "username_text_match": { "mode": "contains", "case_sensitive": false, "patterns": ["bot"] },
+//         "chatmsg_text_match": { "mode": "regex", "patterns": ["discord\\.gg/\\w+"] }
+//       },
+//       "actions": { "mark": ["cheater"], "votekick": true, "announce": "{name} is a bot" }
+//     }
+//   ]
+// }
+//
+// The match modes are equal, contains, starts_with, ends_with, word and regex.
+// A rule that can't be read is skipped with a warning, the rest of the file still loads.
+// Each trigger fires on its own, there's no "match_all" like in TF2BD.
+// "votekick" and "announce" are our own additions, TF2BD has no such actions.
+
+use crate::{
+    models::{tf2bd_playerlist::flag_from_attribute, PlayerFlags, ALL_FLAGS},
+    utils::BoxResult,
+};
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::prelude::*;
+
+/// Stored in the same directory as the settings.json file
+const RULES_FILENAME: &str = "rules.json";
+
+#[derive(Deserialize, Debug)]
+struct RulesFile {
+    /// Read one at a time, so one bad rule doesn't take the others with it
+    #[serde(default)]
+    rules: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RuleDefinition {
+    description: String,
+    #[serde(default)]
+    triggers: TriggersDefinition,
+    #[serde(default)]
+    actions: RuleActionsDefinition,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct TriggersDefinition {
+    username_text_match: Option<TextMatchDefinition>,
+    chatmsg_text_match: Option<TextMatchDefinition>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TextMatchDefinition {
+    mode: TextMatchMode,
+    #[serde(default)]
+    case_sensitive: bool,
+    patterns: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct RuleActionsDefinition {
+    /// TF2BD attribute names like "cheater", or our flag names like "Bot"
+    #[serde(default)]
+    mark: Vec<String>,
+    #[serde(default)]
+    votekick: bool,
+    announce: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TextMatchMode {
+    Equal,
+    Contains,
+    StartsWith,
+    EndsWith,
+
+    /// The pattern as whole words, not inside another word
+    Word,
+    Regex,
+}
+
+/// Patterns to match a text with. Non regex patterns are kept in lower case
+/// when the match is case insensitive.
+#[derive(Debug)]
+pub struct TextMatcher {
+    mode: TextMatchMode,
+    case_sensitive: bool,
+    patterns: Vec<String>,
+    regexes: Vec<Regex>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleActions {
+    pub mark: Vec<PlayerFlags>,
+    pub votekick: bool,
+
+    /// Chat message to say when the rule fires. {name} is replaced with the player's name.
+    pub announce: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Rule {
+    pub description: String,
+    name_matcher: Option<TextMatcher>,
+    chat_matcher: Option<TextMatcher>,
+    pub actions: RuleActions,
+}
+
+#[derive(Debug, Default)]
+pub struct RuleEngine {
+    rules: Vec<Rule>,
+}
+
+impl TextMatcher {
+    fn new(definition: TextMatchDefinition) -> BoxResult<Self> {
+        let mut regexes = vec![];
+        let mut patterns = vec![];
+
+        for pattern in definition.patterns {
+            if definition.mode == TextMatchMode::Word {
+                let word = format!(r"(?:^|\W){}(?:\W|$)", regex::escape(&pattern));
+                let regex = RegexBuilder::new(&word)
+                    .case_insensitive(!definition.case_sensitive)
+                    .build()?;
+                regexes.push(regex);
+            } else if definition.mode == TextMatchMode::Regex {
+                let regex = RegexBuilder::new(&pattern)
+                    .case_insensitive(!definition.case_sensitive)
+                    .build()?;
+                regexes.push(regex);
+            } else if definition.case_sensitive {
+                patterns.push(pattern);
+            } else {
+                patterns.push(pattern.to_lowercase());
+            }
+        }
+
+        Ok(Self {
+            mode: definition.mode,
+            case_sensitive: definition.case_sensitive,
+            patterns,
+            regexes,
+        })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        if matches!(self.mode, TextMatchMode::Word | TextMatchMode::Regex) {
+            return self.regexes.iter().any(|regex| regex.is_match(text));
+        }
+
+        let text = if self.case_sensitive {
+            text.to_string()
+        } else {
+            text.to_lowercase()
+        };
+
+        self.patterns.iter().any(|pattern| match self.mode {
+            TextMatchMode::Equal => text == *pattern,
+            TextMatchMode::Contains => text.contains(pattern.as_str()),
+            TextMatchMode::StartsWith => text.starts_with(pattern.as_str()),
+            TextMatchMode::EndsWith => text.ends_with(pattern.as_str()),
+            TextMatchMode::Word | TextMatchMode::Regex => false,
+        })
+    }
+}
+
+fn flag_from_name(name: &str) -> Option<PlayerFlags> {
+    flag_from_attribute(name).or_else(|| {
+        ALL_FLAGS
+            .into_iter()
+            .find(|flag| format!("{:?}", flag).eq_ignore_ascii_case(name))
+    })
+}
+
+impl Rule {
+    fn new(definition: RuleDefinition) -> BoxResult<Self> {
+        let mut mark = vec![];
+        for name in definition.actions.mark {
+            match flag_from_name(&name) {
+                Some(flag) => mark.push(flag),
+                None => log::warn!("Rule '{}': unknown flag '{}'", definition.description, name),
+            }
+        }
+
+        Ok(Self {
+            name_matcher: definition
+                .triggers
+                .username_text_match
+                .map(TextMatcher::new)
+                .transpose()?,
+            chat_matcher: definition
+                .triggers
+                .chatmsg_text_match
+                .map(TextMatcher::new)
+                .transpose()?,
+            actions: RuleActions {
+                mark,
+                votekick: definition.actions.votekick,
+                announce: definition.actions.announce,
+            },
+            description: definition.description,
+        })
+    }
+}
+
+impl RuleEngine {
+    /// Loads the rules.json file from the current directory.
+    /// Without the file there are no rules.
+    pub fn load_or_default() -> Self {
+        let result = File::open(RULES_FILENAME).map(|mut f| {
+            let mut json = String::new();
+            f.read_to_string(&mut json).map(|_| json)
+        });
+
+        match result {
+            Ok(Ok(json)) => match Self::from_json(&json) {
+                Ok(engine) => {
+                    log::info!(
+                        "Loaded {} rules from {}",
+                        engine.rules.len(),
+                        RULES_FILENAME
+                    );
+                    engine
+                }
+                Err(error) => {
+                    log::error!("Rules file {} is not valid: {}", RULES_FILENAME, error);
+                    Self::default()
+                }
+            },
+            _ => {
+                log::info!("No rules file {}, no rules are used", RULES_FILENAME);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn from_json(json: &str) -> BoxResult<Self> {
+        let file: RulesFile = serde_json::from_str(json)?;
+
+        let mut rules = vec![];
+        for (index, value) in file.rules.into_iter().enumerate() {
+            let description = value
+                .get("description")
+                .and_then(|d| d.as_str())
+                .map_or_else(|| format!("#{}", index + 1), str::to_string);
+
+            let rule = serde_json::from_value(value)
+                .map_err(|error| error.into())
+                .and_then(Rule::new);
+            match rule {
+                Ok(rule) => rules.push(rule),
+                Err(error) => log::warn!("Skipping rule '{}': {}", description, error),
+            }
+        }
+
+        Ok(Self { rules })
+    }
+
+    /// The rules whose name trigger matches the player name
+    pub fn match_name(&self, name: &str) -> Vec<&Rule> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.name_matcher.as_ref().is_some_and(|m| m.is_match(name)))
+            .collect()
+    }
+
+    /// The rules whose chat trigger matches the chat message
+    pub fn match_chat(&self, message: &str) -> Vec<&Rule> {
+        self.rules
+            .iter()
+            .filter(|rule| {
+                rule.chat_matcher
+                    .as_ref()
+                    .is_some_and(|m| m.is_match(message))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RULES: &str = include_str!("../../../testdata/rules.sample.json");
+
+    fn descriptions(rules: Vec<&Rule>) -> Vec<&str> {
+        rules.iter().map(|r| r.description.as_str()).collect()
+    }
+
+    #[test]
+    fn test_load_rules() {
+        let engine = RuleEngine::from_json(SAMPLE_RULES).unwrap();
+        assert_eq!(engine.rules.len(), 4);
+
+        let rule = &engine.rules[0];
+        assert_eq!(rule.actions.mark, vec![PlayerFlags::Bot]);
+        assert!(rule.actions.votekick);
+        assert_eq!(rule.actions.announce.as_deref(), Some("{name} is a bot"));
+
+        // TF2BD attribute names work too, unknown flags are skipped
+        let rule = &engine.rules[1];
+        assert_eq!(rule.actions.mark, vec![PlayerFlags::Cheater]);
+        assert!(!rule.actions.votekick);
+    }
+
+    #[test]
+    fn test_match_name() {
+        let engine = RuleEngine::from_json(SAMPLE_RULES).unwrap();
+
+        // Case insensitive substring
+        assert_eq!(
+            descriptions(engine.match_name("OMEGATRONIC Bot 3000")),
+            vec!["Bot names"]
+        );
+        assert!(engine.match_name("Player1").is_empty());
+
+        // Case sensitive equal
+        assert_eq!(descriptions(engine.match_name("MYG)T")), vec!["Exact name"]);
+        assert!(engine.match_name("myg)t").is_empty());
+    }
+
+    #[test]
+    fn test_match_chat() {
+        let engine = RuleEngine::from_json(SAMPLE_RULES).unwrap();
+
+        // Case insensitive regex
+        assert_eq!(
+            descriptions(engine.match_chat("Join DISCORD.gg/abc123 for free hats")),
+            vec!["Spam links"]
+        );
+        assert!(engine.match_chat("discord is down").is_empty());
+
+        // Name rules don't trigger on chat
+        assert!(engine.match_chat("bot").is_empty());
+    }
+
+    #[test]
+    fn test_word_mode() {
+        let json = r#"{ "rules": [ { "description": "Word",
+            "triggers": { "chatmsg_text_match": { "mode": "word", "patterns": ["hack", "free hats"] } } } ] }"#;
+        let engine = RuleEngine::from_json(json).unwrap();
+
+        assert_eq!(
+            descriptions(engine.match_chat("nice HACK, m8")),
+            vec!["Word"]
+        );
+        assert_eq!(descriptions(engine.match_chat("hack")), vec!["Word"]);
+        assert_eq!(
+            descriptions(engine.match_chat("get free hats!")),
+            vec!["Word"]
+        );
+        assert!(engine.match_chat("a shacky aim").is_empty());
+        assert!(engine.match_chat("hacker").is_empty());
+    }
+
+    #[test]
+    fn test_bad_rules_are_skipped() {
+        let json = r#"{ "rules": [
+            { "description": "Bad regex",
+              "triggers": { "chatmsg_text_match": { "mode": "regex", "patterns": ["(unclosed"] } } },
+            { "description": "Unknown mode",
+              "triggers": { "username_text_match": { "mode": "fuzzy", "patterns": ["bot"] } } },
+            { "triggers": { "username_text_match": { "mode": "contains" } } },
+            { "description": "Good",
+              "triggers": { "username_text_match": { "mode": "contains", "patterns": ["bot"] } } }
+        ] }"#;
+        let engine = RuleEngine::from_json(json).unwrap();
+
+        assert_eq!(engine.rules.len(), 1);
+        assert_eq!(descriptions(engine.match_name("a bot")), vec!["Good"]);
+
+        // A file that isn't JSON at all is still an error
+        assert!(RuleEngine::from_json("{ not json").is_err());
+    }
+}
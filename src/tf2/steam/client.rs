@@ -0,0 +1,320 @@
+// HTTP client for the Steam Web API.
+//
+// All calls go through SteamClient::get, which:
+// - retries with backoff when Steam answers 429 Too Many Requests or a 5xx error,
+//   waiting at most MAX_RETRY_TIME in total
+// - counts every request against a budget, Steam allows 100,000 calls a day per key
+// - turns every failure into a SteamApiError instead of panicking
+//
+// The base URL is configurable so the client can be tested against a local server.
+
+use reqwest::{blocking::Client, header::RETRY_AFTER, StatusCode};
+use serde::de::DeserializeOwned;
+use std::{
+    fmt,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+pub const DEFAULT_STEAM_API_URL: &str = "https://api.steampowered.com";
+
+/// Steam accepts at most this many steamids in one GetPlayerSummaries or GetPlayerBans call
+pub const MAX_IDS_PER_REQUEST: usize = 100;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Retries after the first attempt
+const MAX_RETRIES: u32 = 3;
+
+/// Delay before the first retry, doubled for each retry after that
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Never wait longer than this between retries, even if Steam asks for it
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// The retries of one call give up when they would have waited longer than this in total
+const MAX_RETRY_TIME: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum SteamApiError {
+    /// No Steam API key in settings.json
+    NoApiKey,
+
+    /// The request budget is used up until the time window resets
+    BudgetExhausted,
+
+    /// The request could not be sent or the reply not read
+    Http(reqwest::Error),
+
+    /// Steam answered with an error status, after any retries
+    Status(StatusCode),
+
+    /// The reply was not the JSON we expected
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for SteamApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SteamApiError::NoApiKey => write!(f, "No Steam API key"),
+            SteamApiError::BudgetExhausted => write!(f, "Steam API request budget is used up"),
+            SteamApiError::Http(e) => write!(f, "HTTP error: {}", e),
+            SteamApiError::Status(status) => write!(f, "Steam API replied {}", status),
+            SteamApiError::Parse(e) => write!(f, "Could not parse reply: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SteamApiError {}
+
+impl From<reqwest::Error> for SteamApiError {
+    fn from(e: reqwest::Error) -> Self {
+        SteamApiError::Http(e)
+    }
+}
+
+impl From<serde_json::Error> for SteamApiError {
+    fn from(e: serde_json::Error) -> Self {
+        SteamApiError::Parse(e)
+    }
+}
+
+/// Max number of requests per time window. The count starts over when the window has passed.
+#[derive(Debug)]
+pub struct RequestBudget {
+    max_requests: u32,
+    window: Duration,
+    window_start: Instant,
+    used: u32,
+}
+
+impl RequestBudget {
+    pub fn new(max_requests: u32, window: Duration) -> Self {
+        Self {
+            max_requests,
+            window,
+            window_start: Instant::now(),
+            used: 0,
+        }
+    }
+
+    /// A budget of max_requests a day
+    pub fn per_day(max_requests: u32) -> Self {
+        Self::new(max_requests, Duration::from_secs(24 * 60 * 60))
+    }
+
+    /// Uses one request from the budget. Returns false if there is none left.
+    fn try_take(&mut self) -> bool {
+        if self.window_start.elapsed() >= self.window {
+            self.window_start = Instant::now();
+            self.used = 0;
+        }
+
+        if self.used >= self.max_requests {
+            return false;
+        }
+
+        self.used += 1;
+        true
+    }
+}
+
+pub struct SteamClient {
+    base_url: String,
+    api_key: String,
+    client: Client,
+    budget: RequestBudget,
+    retry_delay: Duration,
+    max_retry_time: Duration,
+}
+
+impl SteamClient {
+    pub fn new(base_url: &str, api_key: &str, budget: RequestBudget) -> Self {
+        let client = Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            client,
+            budget,
+            retry_delay: RETRY_DELAY,
+            max_retry_time: MAX_RETRY_TIME,
+        }
+    }
+
+    pub fn has_key(&self) -> bool {
+        !self.api_key.is_empty()
+    }
+
+    /// GETs an API method like "ISteamUser/GetPlayerBans/v1" and parses the JSON reply.
+    /// The key is added to the query.
+    pub fn get<T: DeserializeOwned>(
+        &mut self,
+        method: &str,
+        query: &[(&str, String)],
+    ) -> Result<T, SteamApiError> {
+        if !self.has_key() {
+            return Err(SteamApiError::NoApiKey);
+        }
+
+        let url = format!("{}/{}/", self.base_url, method);
+        let mut attempt = 0;
+        let mut waited = Duration::ZERO;
+
+        loop {
+            if !self.budget.try_take() {
+                return Err(SteamApiError::BudgetExhausted);
+            }
+
+            log::debug!("Requesting: {} {:?}", url, query);
+            let response = self
+                .client
+                .get(&url)
+                .query(&[("key", self.api_key.as_str())])
+                .query(query)
+                .send()?;
+
+            let status = response.status();
+            if status.is_success() {
+                let json = response.text()?;
+                return Ok(serde_json::from_str(&json)?);
+            }
+
+            let retry = status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+            if !retry || attempt >= MAX_RETRIES {
+                return Err(SteamApiError::Status(status));
+            }
+
+            // Use Retry-After if Steam sends it, else back off exponentially
+            let delay = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<u64>().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(self.retry_delay * 2u32.pow(attempt))
+                .min(MAX_RETRY_DELAY);
+
+            if waited + delay > self.max_retry_time {
+                log::warn!(
+                    "Steam API replied {} for {}, giving up after {:?} of retries",
+                    status,
+                    method,
+                    waited
+                );
+                return Err(SteamApiError::Status(status));
+            }
+
+            log::warn!(
+                "Steam API replied {} for {}, retrying in {:?}",
+                status,
+                method,
+                delay
+            );
+            sleep(delay);
+            waited += delay;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_server;
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Reply {
+        ok: bool,
+    }
+
+    fn client(url: &str, budget: u32) -> SteamClient {
+        let mut client = SteamClient::new(url, "KEY", RequestBudget::per_day(budget));
+        client.retry_delay = Duration::from_millis(1);
+        client
+    }
+
+    #[test]
+    fn test_retry_on_429_and_5xx() {
+        let (url, requests) = test_server::start(vec![
+            ("429 Too Many Requests", String::new()),
+            ("503 Service Unavailable", String::new()),
+            ("200 OK", r#"{"ok": true}"#.to_string()),
+        ]);
+
+        let mut client = client(&url, 100);
+        let reply: Reply = client
+            .get("ISteamUser/Test/v1", &[("steamids", "1".to_string())])
+            .unwrap();
+        assert_eq!(reply, Reply { ok: true });
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[0].starts_with("GET /ISteamUser/Test/v1/?key=KEY&steamids=1 "));
+    }
+
+    #[test]
+    fn test_retry_time_is_bounded() {
+        let (url, requests) = test_server::start(vec![
+            ("503 Service Unavailable", String::new()),
+            ("503 Service Unavailable", String::new()),
+            ("503 Service Unavailable", String::new()),
+        ]);
+
+        // Waits 1 ms, then 2 ms would go past the limit
+        let mut client = client(&url, 100);
+        client.max_retry_time = Duration::from_millis(2);
+        let result = client.get::<Reply>("ISteamUser/Test/v1", &[]);
+        assert!(matches!(
+            result,
+            Err(SteamApiError::Status(StatusCode::SERVICE_UNAVAILABLE))
+        ));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_errors() {
+        let (url, _requests) = test_server::start(vec![
+            ("403 Forbidden", String::new()),
+            ("200 OK", "<html>".to_string()),
+        ]);
+        let mut client = client(&url, 100);
+
+        // Client errors are not retried
+        let result = client.get::<Reply>("ISteamUser/Test/v1", &[]);
+        assert!(matches!(
+            result,
+            Err(SteamApiError::Status(StatusCode::FORBIDDEN))
+        ));
+
+        let result = client.get::<Reply>("ISteamUser/Test/v1", &[]);
+        assert!(matches!(result, Err(SteamApiError::Parse(_))));
+
+        let mut no_key = SteamClient::new(&url, "", RequestBudget::per_day(100));
+        let result = no_key.get::<Reply>("ISteamUser/Test/v1", &[]);
+        assert!(matches!(result, Err(SteamApiError::NoApiKey)));
+    }
+
+    #[test]
+    fn test_budget() {
+        let (url, requests) = test_server::start(vec![
+            ("500 Internal Server Error", String::new()),
+            ("500 Internal Server Error", String::new()),
+        ]);
+
+        // The retry uses the last request of the budget
+        let mut client = client(&url, 2);
+        let result = client.get::<Reply>("ISteamUser/Test/v1", &[]);
+        assert!(matches!(result, Err(SteamApiError::BudgetExhausted)));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+
+        let mut budget = RequestBudget::new(1, Duration::ZERO);
+        assert!(budget.try_take());
+        // Window has passed, so the count starts over
+        assert!(budget.try_take());
+    }
+}
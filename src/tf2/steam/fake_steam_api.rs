@@ -0,0 +1,129 @@
+use super::{
+    client::SteamApiError, SteamApi, SteamApiFriend, SteamApiOwnedGame, SteamApiOwnedGames,
+    SteamApiPlayer, SteamApiPlayerBans, TF2_APPID,
+};
+use crate::models::steamid::SteamID;
+use chrono::{DateTime, Local};
+use std::collections::HashMap;
+
+/// A SteamApi with canned replies and no network access, for tests.
+/// Players without canned data are unknown to Steam, or private.
+#[derive(Default)]
+pub struct FakeSteamApi {
+    pub summaries: HashMap<SteamID, SteamApiPlayer>,
+    pub bans: HashMap<SteamID, SteamApiPlayerBans>,
+
+    /// TF2 minutes played, players not here have private game details
+    pub tf2_minutes: HashMap<SteamID, u32>,
+
+    /// Players not here have a private friend list
+    pub friend_lists: HashMap<SteamID, Vec<SteamID>>,
+
+    /// Number of calls made, to check what would have cost API calls
+    pub calls: usize,
+}
+
+impl FakeSteamApi {
+    pub fn add_player(&mut self, steamid: SteamID, name: &str, created: DateTime<Local>) {
+        self.summaries.insert(
+            steamid,
+            SteamApiPlayer {
+                steamid: steamid.to_u64().to_string(),
+                personaname: name.to_string(),
+                avatar: String::new(),
+                avatarmedium: String::new(),
+                avatarfull: String::new(),
+                timecreated: Some(created.timestamp() as u64),
+            },
+        );
+    }
+
+    pub fn add_bans(&mut self, steamid: SteamID, vac_bans: u32, game_bans: u32) {
+        self.bans.insert(
+            steamid,
+            SteamApiPlayerBans {
+                steam_id: steamid.to_u64().to_string(),
+                community_banned: false,
+                number_of_vac_bans: vac_bans,
+                days_since_last_ban: 0,
+                number_of_game_bans: game_bans,
+                economy_ban: "none".to_string(),
+            },
+        );
+    }
+}
+
+impl SteamApi for FakeSteamApi {
+    fn has_key(&self) -> bool {
+        true
+    }
+
+    fn get_player_summaries(
+        &mut self,
+        steamids: Vec<SteamID>,
+    ) -> Result<Vec<SteamApiPlayer>, SteamApiError> {
+        self.calls += 1;
+        Ok(steamids
+            .iter()
+            .filter_map(|steamid| self.summaries.get(steamid).cloned())
+            .collect())
+    }
+
+    fn get_player_bans(
+        &mut self,
+        steamids: Vec<SteamID>,
+    ) -> Result<Vec<SteamApiPlayerBans>, SteamApiError> {
+        self.calls += 1;
+
+        // Steam replies with no bans for every known player
+        Ok(steamids
+            .iter()
+            .filter(|steamid| self.summaries.contains_key(steamid))
+            .map(|steamid| {
+                self.bans
+                    .get(steamid)
+                    .cloned()
+                    .unwrap_or(SteamApiPlayerBans {
+                        steam_id: steamid.to_u64().to_string(),
+                        community_banned: false,
+                        number_of_vac_bans: 0,
+                        days_since_last_ban: 0,
+                        number_of_game_bans: 0,
+                        economy_ban: "none".to_string(),
+                    })
+            })
+            .collect())
+    }
+
+    fn get_owned_games(&mut self, steamid: SteamID) -> Result<SteamApiOwnedGames, SteamApiError> {
+        self.calls += 1;
+        Ok(match self.tf2_minutes.get(&steamid) {
+            Some(minutes) => SteamApiOwnedGames {
+                game_count: Some(1),
+                games: vec![SteamApiOwnedGame {
+                    appid: TF2_APPID,
+                    playtime_forever: *minutes,
+                }],
+            },
+            None => SteamApiOwnedGames {
+                game_count: None,
+                games: Vec::new(),
+            },
+        })
+    }
+
+    fn get_friend_list(
+        &mut self,
+        steamid: SteamID,
+    ) -> Result<Option<Vec<SteamApiFriend>>, SteamApiError> {
+        self.calls += 1;
+        Ok(self.friend_lists.get(&steamid).map(|friends| {
+            friends
+                .iter()
+                .map(|friend| SteamApiFriend {
+                    steamid: friend.to_u64().to_string(),
+                })
+                .collect()
+        }))
+    }
+}
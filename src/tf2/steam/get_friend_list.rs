@@ -0,0 +1,40 @@
+use crate::models::steamid::SteamID;
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+use super::{
+    client::{SteamApiError, SteamClient},
+    SteamApiFriend,
+};
+
+#[derive(Deserialize)]
+struct Friends {
+    #[serde(default)]
+    friends: Vec<SteamApiFriend>,
+}
+
+#[derive(Deserialize)]
+struct GetFriendListApiResponse {
+    friendslist: Friends,
+}
+
+/// Returns Ok(None) if the friend list is private
+pub fn get_friend_list(
+    client: &mut SteamClient,
+    steamid: SteamID,
+) -> Result<Option<Vec<SteamApiFriend>>, SteamApiError> {
+    let reply = client.get::<GetFriendListApiResponse>(
+        "ISteamUser/GetFriendList/v1",
+        &[
+            ("steamid", steamid.to_u64().to_string()),
+            ("relationship", "friend".to_string()),
+        ],
+    );
+
+    match reply {
+        Ok(reply) => Ok(Some(reply.friendslist.friends)),
+        // Private friend lists gives 401 Unauthorized
+        Err(SteamApiError::Status(StatusCode::UNAUTHORIZED)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
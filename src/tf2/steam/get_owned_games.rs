@@ -0,0 +1,32 @@
+use crate::models::steamid::SteamID;
+use serde::Deserialize;
+
+use super::{
+    client::{SteamApiError, SteamClient},
+    SteamApiOwnedGames,
+};
+
+/// TF2's appid
+pub const TF2_APPID: u32 = 440;
+
+#[derive(Deserialize)]
+struct GetOwnedGamesApiResponse {
+    response: SteamApiOwnedGames,
+}
+
+/// Only asks for TF2. Unlike the other calls this one takes a single SteamID.
+pub fn get_owned_games(
+    client: &mut SteamClient,
+    steamid: SteamID,
+) -> Result<SteamApiOwnedGames, SteamApiError> {
+    let reply: GetOwnedGamesApiResponse = client.get(
+        "IPlayerService/GetOwnedGames/v1",
+        &[
+            ("steamid", steamid.to_u64().to_string()),
+            ("include_played_free_games", "1".to_string()),
+            ("appids_filter[0]", TF2_APPID.to_string()),
+        ],
+    )?;
+
+    Ok(reply.response)
+}
@@ -0,0 +1,42 @@
+use crate::models::steamid::SteamID;
+use serde::Deserialize;
+
+use super::{
+    client::{SteamApiError, SteamClient, MAX_IDS_PER_REQUEST},
+    SteamApiPlayerBans,
+};
+
+#[derive(Deserialize)]
+struct GetPlayerBansApiResponse {
+    players: Vec<SteamApiPlayerBans>,
+}
+
+pub fn get_player_bans(
+    client: &mut SteamClient,
+    steamids: Vec<SteamID>,
+) -> Result<Vec<SteamApiPlayerBans>, SteamApiError> {
+    let mut bans = Vec::new();
+    let mut error = None;
+
+    for chunk in steamids.chunks(MAX_IDS_PER_REQUEST) {
+        let steamids: Vec<String> = chunk.iter().map(|s| s.to_u64().to_string()).collect();
+
+        let reply: Result<GetPlayerBansApiResponse, _> = client.get(
+            "ISteamUser/GetPlayerBans/v1",
+            &[("steamids", steamids.join(","))],
+        );
+        match reply {
+            Ok(reply) => bans.extend(reply.players),
+            Err(e) => {
+                log::warn!("GetPlayerBans failed for {} players: {}", chunk.len(), e);
+                error = Some(e);
+            }
+        }
+    }
+
+    // The chunks that were fetched are kept, it's only an error if none were
+    match error {
+        Some(e) if bans.is_empty() => Err(e),
+        _ => Ok(bans),
+    }
+}
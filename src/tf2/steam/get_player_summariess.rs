@@ -1,8 +1,10 @@
 use crate::models::steamid::SteamID;
-use reqwest::blocking::get;
 use serde::Deserialize;
 
-use super::SteamApiPlayer;
+use super::{
+    client::{SteamApiError, SteamClient, MAX_IDS_PER_REQUEST},
+    SteamApiPlayer,
+};
 
 #[derive(Deserialize)]
 struct Players {
@@ -15,33 +17,35 @@ struct GetPlayerSummariesApiResponse {
 }
 
 pub fn get_player_summaries(
-    steam_api_key: &String,
+    client: &mut SteamClient,
     steamids: Vec<SteamID>,
-) -> Option<Vec<SteamApiPlayer>> {
-    let steamids: Vec<String> = steamids.iter().map(|s| s.to_u64().to_string()).collect();
-    let steamids = steamids.join(",");
+) -> Result<Vec<SteamApiPlayer>, SteamApiError> {
+    let mut players = Vec::new();
+    let mut error = None;
 
-    let url = format!(
-        "https://api.steampowered.com/ISteamUser/GetPlayerSummaries/v2/?key={}&steamids={}",
-        steam_api_key, steamids
-    );
+    for chunk in steamids.chunks(MAX_IDS_PER_REQUEST) {
+        let steamids: Vec<String> = chunk.iter().map(|s| s.to_u64().to_string()).collect();
 
-    log::debug!("Requesting: {}", url);
-    let response = get(url);
-    match response {
-        Ok(response) => {
-            // log::debug!("Reply: {:?}", response);
-            // let reply = response.text().unwrap();
-
-            // println!("{}", reply);
-            // return None;
-            let reply: GetPlayerSummariesApiResponse = response.json().unwrap();
-            let players = reply.response.players;
-            Some(players)
-        }
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            None
+        let reply: Result<GetPlayerSummariesApiResponse, _> = client.get(
+            "ISteamUser/GetPlayerSummaries/v2",
+            &[("steamids", steamids.join(","))],
+        );
+        match reply {
+            Ok(reply) => players.extend(reply.response.players),
+            Err(e) => {
+                log::warn!(
+                    "GetPlayerSummaries failed for {} players: {}",
+                    chunk.len(),
+                    e
+                );
+                error = Some(e);
+            }
         }
     }
+
+    // The chunks that were fetched are kept, it's only an error if none were
+    match error {
+        Some(e) if players.is_empty() => Err(e),
+        _ => Ok(players),
+    }
 }
@@ -4,23 +4,116 @@
 //   - Can take multiple IDs.
 //   - For account name, avatar info and account age.
 // - GetFriendList
-//   - Array of friend's Steam ID64s.
+//   - One ID at a time.
+//   - Array of friend's Steam ID64s. 401 Unauthorized if the friend list is private.
 // - GetPlayerBans
+//   - Can take multiple IDs.
+//   - VAC, game, community and economy bans.
+// - GetOwnedGames
+//   - One ID at a time.
+//   - For TF2 playtime. Empty reply if the game details are private.
+//
+// All calls go through the client in client.rs, and all replies are cached on
+// disk per player, see steam_cache.rs. The calls are made on the Steam thread,
+// the lobby thread asks for what's missing, see steam_thread.rs.
 //
 
+pub mod client;
+#[cfg(test)]
+pub mod fake_steam_api;
+mod get_friend_list;
+mod get_owned_games;
+mod get_player_bans;
 mod get_player_summariess;
+mod steam_cache;
+pub mod steam_thread;
 
 use crate::models::{app_settings::AppSettings, steamid::SteamID};
 use chrono::{DateTime, Local, TimeZone};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+use self::client::{RequestBudget, SteamApiError, SteamClient};
+use self::{
+    get_friend_list::get_friend_list,
+    get_owned_games::{get_owned_games, TF2_APPID},
+    get_player_bans::get_player_bans,
+    get_player_summariess::get_player_summaries,
+    steam_cache::SteamCache,
+};
+
+/// The Steam info the lobby needs. Implemented by SteamWebApi, and by
+/// FakeSteamApi in tests so the lobby can be tested without network access.
+pub trait SteamApi {
+    /// Returns true if the Steam API can be used
+    fn has_key(&self) -> bool;
+
+    /// Name, avatar and account age. Unknown steamids are left out of the reply.
+    fn get_player_summaries(
+        &mut self,
+        steamids: Vec<SteamID>,
+    ) -> Result<Vec<SteamApiPlayer>, SteamApiError>;
+
+    /// VAC, game, community and economy bans
+    fn get_player_bans(
+        &mut self,
+        steamids: Vec<SteamID>,
+    ) -> Result<Vec<SteamApiPlayerBans>, SteamApiError>;
 
-use self::get_player_summariess::get_player_summaries;
+    /// The owned games, filtered to only include TF2
+    fn get_owned_games(&mut self, steamid: SteamID) -> Result<SteamApiOwnedGames, SteamApiError>;
 
-pub struct SteamApi {
-    steam_api_key: String,
+    /// Returns Ok(None) if the friend list is private
+    fn get_friend_list(
+        &mut self,
+        steamid: SteamID,
+    ) -> Result<Option<Vec<SteamApiFriend>>, SteamApiError>;
+
+    /// Fetch everything for the player again next time it's asked for
+    fn force_refresh(&mut self, _steamid: SteamID) {}
+
+    /// Called after each round of fetches
+    fn save_cache(&mut self) {}
 }
 
-#[derive(Debug, Deserialize)]
+/// Sent from the lobby thread to the Steam thread, the players missing each kind of info
+#[derive(Debug, Clone, Default)]
+pub struct SteamRequest {
+    /// Drop the cached info for these players before fetching
+    pub refresh: Vec<SteamID>,
+    pub summaries: Vec<SteamID>,
+    pub bans: Vec<SteamID>,
+    pub playtime: Vec<SteamID>,
+    pub friends: Vec<SteamID>,
+}
+
+impl SteamRequest {
+    pub fn is_empty(&self) -> bool {
+        self.refresh.is_empty()
+            && self.summaries.is_empty()
+            && self.bans.is_empty()
+            && self.playtime.is_empty()
+            && self.friends.is_empty()
+    }
+}
+
+/// Sent from the Steam thread back to the lobby thread, one for each kind of info fetched
+#[derive(Debug, Clone)]
+pub enum SteamReply {
+    Summaries(Vec<SteamApiPlayer>),
+    Bans(Vec<SteamApiPlayerBans>),
+    OwnedGames(Vec<(SteamID, SteamApiOwnedGames)>),
+
+    /// None if the friend list is private
+    FriendLists(Vec<(SteamID, Option<Vec<SteamApiFriend>>)>),
+}
+
+/// The Steam Web API, with replies cached on disk
+pub struct SteamWebApi {
+    client: SteamClient,
+    cache: SteamCache,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SteamApiPlayer {
     pub steamid: String,
     pub personaname: String,
@@ -32,21 +125,195 @@ pub struct SteamApiPlayer {
     pub timecreated: Option<u64>,
 }
 
-impl SteamApi {
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SteamApiPlayerBans {
+    pub steam_id: String,
+    pub community_banned: bool,
+    // VACBanned: bool, same as NumberOfVACBans > 0
+    #[serde(rename = "NumberOfVACBans")]
+    pub number_of_vac_bans: u32,
+    pub days_since_last_ban: u32,
+    pub number_of_game_bans: u32,
+    /// "none", "probation" or "banned"
+    pub economy_ban: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SteamApiFriend {
+    pub steamid: String,
+    // relationship: String,
+    // friend_since: u64,
+}
+
+/// Reply from GetOwnedGames. Both fields are missing if the game details are private.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SteamApiOwnedGames {
+    pub game_count: Option<u32>,
+    #[serde(default)]
+    pub games: Vec<SteamApiOwnedGame>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SteamApiOwnedGame {
+    pub appid: u32,
+    /// Minutes
+    pub playtime_forever: u32,
+}
+
+impl SteamWebApi {
     pub fn new(app_settings: &AppSettings) -> Self {
         Self {
-            steam_api_key: app_settings.steam_api_key.clone(),
+            client: SteamClient::new(
+                &app_settings.steam_api_url,
+                &app_settings.steam_api_key,
+                RequestBudget::per_day(app_settings.steam_api_daily_budget),
+            ),
+            cache: SteamCache::load_or_default(&app_settings.steam_cache),
+        }
+    }
+
+    /// Splits the steamids into the cached replies and the steamids that needs fetching
+    fn split_cached<T>(
+        &self,
+        steamids: Vec<SteamID>,
+        get: impl Fn(&SteamCache, SteamID) -> Option<T>,
+    ) -> (Vec<T>, Vec<SteamID>) {
+        let mut cached = Vec::new();
+        let mut missing = Vec::new();
+
+        for steamid in steamids {
+            match get(&self.cache, steamid) {
+                Some(reply) => cached.push(reply),
+                None => missing.push(steamid),
+            }
+        }
+
+        (cached, missing)
+    }
+}
+
+impl SteamApi for SteamWebApi {
+    /// Fetches player summaries from the Steam API for a list of steamdids.
+    /// Only the players not in the cache are fetched. If that fails, the cached players
+    /// are still returned.
+    fn get_player_summaries(
+        &mut self,
+        steamids: Vec<SteamID>,
+    ) -> Result<Vec<SteamApiPlayer>, SteamApiError> {
+        let (mut players, missing) =
+            self.split_cached(steamids, |cache, steamid| cache.summary(steamid).cloned());
+
+        if !missing.is_empty() {
+            let fetched = match get_player_summaries(&mut self.client, missing) {
+                Ok(fetched) => fetched,
+                Err(e) if players.is_empty() => return Err(e),
+                Err(e) => {
+                    log::warn!(
+                        "GetPlayerSummaries failed, using cached players only: {}",
+                        e
+                    );
+                    Vec::new()
+                }
+            };
+
+            for player in fetched {
+                if let Some(steamid) = SteamID::from_u64_string(&player.steamid) {
+                    self.cache.set_summary(steamid, player.clone());
+                }
+                players.push(player);
+            }
+        }
+
+        Ok(players)
+    }
+
+    /// Fetches VAC and game bans from the Steam API for a list of steamids.
+    /// Only the players not in the cache are fetched. If that fails, the cached bans
+    /// are still returned.
+    fn get_player_bans(
+        &mut self,
+        steamids: Vec<SteamID>,
+    ) -> Result<Vec<SteamApiPlayerBans>, SteamApiError> {
+        let (mut bans, missing) =
+            self.split_cached(steamids, |cache, steamid| cache.bans(steamid).cloned());
+
+        if !missing.is_empty() {
+            let fetched = match get_player_bans(&mut self.client, missing) {
+                Ok(fetched) => fetched,
+                Err(e) if bans.is_empty() => return Err(e),
+                Err(e) => {
+                    log::warn!("GetPlayerBans failed, using cached bans only: {}", e);
+                    Vec::new()
+                }
+            };
+
+            for player_bans in fetched {
+                if let Some(steamid) = SteamID::from_u64_string(&player_bans.steam_id) {
+                    self.cache.set_bans(steamid, player_bans.clone());
+                }
+                bans.push(player_bans);
+            }
+        }
+
+        Ok(bans)
+    }
+
+    /// Fetches the owned games from the Steam API, filtered to only include TF2
+    fn get_owned_games(&mut self, steamid: SteamID) -> Result<SteamApiOwnedGames, SteamApiError> {
+        if let Some(owned_games) = self.cache.owned_games(steamid) {
+            return Ok(owned_games.clone());
         }
+
+        let owned_games = get_owned_games(&mut self.client, steamid)?;
+        self.cache.set_owned_games(steamid, owned_games.clone());
+        Ok(owned_games)
+    }
+
+    /// Fetches the friend list from the Steam API.
+    /// Returns Ok(None) if the friend list is private.
+    fn get_friend_list(
+        &mut self,
+        steamid: SteamID,
+    ) -> Result<Option<Vec<SteamApiFriend>>, SteamApiError> {
+        if let Some(friends) = self.cache.friend_list(steamid) {
+            return Ok(friends.clone());
+        }
+
+        let friends = get_friend_list(&mut self.client, steamid)?;
+        self.cache.set_friend_list(steamid, friends.clone());
+        Ok(friends)
     }
 
-    /// Fetches player summaries from the Steam API for a list of steamdids
-    pub fn get_player_summaries(&mut self, steamids: Vec<SteamID>) -> Option<Vec<SteamApiPlayer>> {
-        get_player_summaries(&self.steam_api_key, steamids)
+    /// Forget the cached replies for a player, so everything is fetched again
+    fn force_refresh(&mut self, steamid: SteamID) {
+        self.cache.invalidate(steamid);
+    }
+
+    /// Saves the replies fetched since last time to the on-disk cache
+    fn save_cache(&mut self) {
+        self.cache.save();
     }
 
     /// Returns true if the Steam API key is set
-    pub fn has_key(&self) -> bool {
-        !self.steam_api_key.is_empty()
+    fn has_key(&self) -> bool {
+        self.client.has_key()
+    }
+}
+
+impl SteamApiOwnedGames {
+    /// TF2 playtime in minutes, or None if the game details are private
+    pub fn get_tf2_playtime(&self) -> Option<u32> {
+        self.game_count?;
+
+        let minutes = self
+            .games
+            .iter()
+            .find(|game| game.appid == TF2_APPID)
+            .map(|game| game.playtime_forever)
+            .unwrap_or(0);
+
+        Some(minutes)
     }
 }
 
@@ -61,3 +328,111 @@ impl SteamApiPlayer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_server;
+
+    #[test]
+    fn test_parse_player_bans() {
+        let json = r#"{"SteamId":"76561197960435530","CommunityBanned":false,"VACBanned":true,"NumberOfVACBans":2,"DaysSinceLastBan":120,"NumberOfGameBans":1,"EconomyBan":"none"}"#;
+        let bans: SteamApiPlayerBans = serde_json::from_str(json).unwrap();
+
+        assert_eq!(bans.steam_id, "76561197960435530");
+        assert!(!bans.community_banned);
+        assert_eq!(bans.number_of_vac_bans, 2);
+        assert_eq!(bans.days_since_last_ban, 120);
+        assert_eq!(bans.number_of_game_bans, 1);
+        assert_eq!(bans.economy_ban, "none");
+    }
+
+    #[test]
+    fn test_parse_owned_games() {
+        let json = r#"{"game_count":1,"games":[{"appid":440,"playtime_forever":6123,"playtime_windows_forever":6123}]}"#;
+        let games: SteamApiOwnedGames = serde_json::from_str(json).unwrap();
+        assert_eq!(games.get_tf2_playtime(), Some(6123));
+
+        // Public, but never played TF2
+        let games: SteamApiOwnedGames = serde_json::from_str(r#"{"game_count":0}"#).unwrap();
+        assert_eq!(games.get_tf2_playtime(), Some(0));
+
+        // Game details are private
+        let games: SteamApiOwnedGames = serde_json::from_str("{}").unwrap();
+        assert_eq!(games.get_tf2_playtime(), None);
+    }
+
+    fn test_client(url: &str) -> SteamClient {
+        SteamClient::new(url, "KEY", RequestBudget::per_day(100))
+    }
+
+    #[test]
+    fn test_bans_in_chunks_of_100() {
+        let reply = |first: u64, count: u64| {
+            let players: Vec<String> = (first..first + count)
+                .map(|id| {
+                    format!(
+                        r#"{{"SteamId":"{}","CommunityBanned":false,"NumberOfVACBans":0,"DaysSinceLastBan":0,"NumberOfGameBans":0,"EconomyBan":"none"}}"#,
+                        id
+                    )
+                })
+                .collect();
+            format!(r#"{{"players":[{}]}}"#, players.join(","))
+        };
+
+        let first = 76561197960265729;
+        let (url, requests) = test_server::start(vec![
+            ("200 OK", reply(first, 100)),
+            ("200 OK", reply(first + 100, 50)),
+        ]);
+
+        let steamids: Vec<SteamID> = (first..first + 150).map(SteamID::from_u64).collect();
+        let bans = get_player_bans(&mut test_client(&url), steamids).unwrap();
+        assert_eq!(bans.len(), 150);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        // Commas are URL encoded
+        assert_eq!(requests[0].matches("%2C").count(), 99);
+        assert_eq!(requests[1].matches("%2C").count(), 49);
+    }
+
+    #[test]
+    fn test_failed_chunk_keeps_the_others() {
+        let first = 76561197960265729;
+        let reply = format!(
+            r#"{{"players":[{{"SteamId":"{}","CommunityBanned":false,"NumberOfVACBans":0,"DaysSinceLastBan":0,"NumberOfGameBans":0,"EconomyBan":"none"}}]}}"#,
+            first + 100
+        );
+        let (url, _requests) =
+            test_server::start(vec![("400 Bad Request", String::new()), ("200 OK", reply)]);
+
+        let steamids: Vec<SteamID> = (first..first + 150).map(SteamID::from_u64).collect();
+        let bans = get_player_bans(&mut test_client(&url), steamids).unwrap();
+        assert_eq!(bans.len(), 1);
+
+        // Nothing fetched at all is an error
+        let (url, _requests) = test_server::start(vec![("400 Bad Request", String::new())]);
+        let steamids = vec![SteamID::from_u64(first)];
+        assert!(get_player_bans(&mut test_client(&url), steamids).is_err());
+    }
+
+    #[test]
+    fn test_private_friend_list() {
+        let (url, _requests) = test_server::start(vec![
+            ("401 Unauthorized", "<html>401 Unauthorized</html>".to_string()),
+            (
+                "200 OK",
+                r#"{"friendslist":{"friends":[{"steamid":"76561197960435530","relationship":"friend","friend_since":1400000000}]}}"#
+                    .to_string(),
+            ),
+        ]);
+        let mut client = test_client(&url);
+        let steamid = SteamID::from_u64(76561197994359533);
+
+        assert!(get_friend_list(&mut client, steamid).unwrap().is_none());
+
+        let friends = get_friend_list(&mut client, steamid).unwrap().unwrap();
+        assert_eq!(friends[0].steamid, "76561197960435530");
+    }
+}
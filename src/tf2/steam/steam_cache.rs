@@ -0,0 +1,326 @@
+// On-disk cache of Steam API replies, so players we have seen before don't
+// cost any API calls, also after a restart.
+//
+// Each kind of reply has its own time to live, set in settings.json. Replies
+// older than that are fetched again. Players not seen for longer than the
+// longest TTL are dropped from the file when it's loaded.
+
+use super::{SteamApiFriend, SteamApiOwnedGames, SteamApiPlayer, SteamApiPlayerBans};
+use crate::{
+    models::{app_settings::SteamCacheSettings, steamid::SteamID},
+    utils::BoxResult,
+};
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+
+/// Stored in the same directory as the settings.json file
+const STEAM_CACHE_FILENAME: &str = "steam_cache.json";
+
+/// A reply and when it was fetched
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Cached<T> {
+    fetched: DateTime<Local>,
+    data: T,
+}
+
+/// Everything cached for one player
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CachedPlayer {
+    steamid: SteamID,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    summary: Option<Cached<SteamApiPlayer>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bans: Option<Cached<SteamApiPlayerBans>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    owned_games: Option<Cached<SteamApiOwnedGames>>,
+
+    /// The data is None if the friend list is private
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    friend_list: Option<Cached<Option<Vec<SteamApiFriend>>>>,
+}
+
+/// The on disk format of the cache
+#[derive(Serialize, Deserialize, Default)]
+struct SteamCacheFile {
+    players: Vec<CachedPlayer>,
+}
+
+#[derive(Debug, Default)]
+pub struct SteamCache {
+    filename: String,
+    settings: SteamCacheSettings,
+    players: HashMap<SteamID, CachedPlayer>,
+
+    /// True if there are changes not yet saved
+    dirty: bool,
+}
+
+impl<T> Cached<T> {
+    fn new(data: T) -> Self {
+        Self {
+            fetched: Local::now(),
+            data,
+        }
+    }
+}
+
+/// The cached data if it's younger than the TTL
+fn fresh<T>(cached: &Option<Cached<T>>, ttl_hours: u64) -> Option<&T> {
+    cached
+        .as_ref()
+        .filter(|c| Local::now() - c.fetched < Duration::hours(ttl_hours as i64))
+        .map(|c| &c.data)
+}
+
+impl CachedPlayer {
+    fn new(steamid: SteamID) -> Self {
+        Self {
+            steamid,
+            summary: None,
+            bans: None,
+            owned_games: None,
+            friend_list: None,
+        }
+    }
+
+    /// When anything was last fetched for this player
+    fn last_fetched(&self) -> Option<DateTime<Local>> {
+        [
+            self.summary.as_ref().map(|c| c.fetched),
+            self.bans.as_ref().map(|c| c.fetched),
+            self.owned_games.as_ref().map(|c| c.fetched),
+            self.friend_list.as_ref().map(|c| c.fetched),
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+    }
+}
+
+impl SteamCache {
+    /// Tries to load the steam_cache.json file from the current directory.
+    /// If the file don't exist or can't be read, start with an empty cache.
+    pub fn load_or_default(settings: &SteamCacheSettings) -> Self {
+        match Self::load(STEAM_CACHE_FILENAME, settings) {
+            Ok(cache) => cache,
+            Err(error) => {
+                log::info!(
+                    "No Steam cache loaded from {}: {}. Starting with an empty one.",
+                    STEAM_CACHE_FILENAME,
+                    error
+                );
+
+                Self {
+                    filename: STEAM_CACHE_FILENAME.to_string(),
+                    settings: settings.clone(),
+                    ..Default::default()
+                }
+            }
+        }
+    }
+
+    pub fn load(filename: &str, settings: &SteamCacheSettings) -> BoxResult<Self> {
+        let mut f = File::open(filename)?;
+        let mut json = String::new();
+        f.read_to_string(&mut json)?;
+        let file: SteamCacheFile = serde_json::from_str(&json)?;
+
+        // Drop players whose every reply has expired
+        let max_age = Duration::hours(settings.max_hours() as i64);
+        let players: HashMap<SteamID, CachedPlayer> = file
+            .players
+            .into_iter()
+            .filter(|p| p.last_fetched().is_some_and(|f| Local::now() - f < max_age))
+            .map(|p| (p.steamid, p))
+            .collect();
+
+        log::info!(
+            "Loaded {} players from Steam cache {}",
+            players.len(),
+            filename
+        );
+
+        Ok(Self {
+            filename: filename.to_string(),
+            settings: settings.clone(),
+            players,
+            dirty: false,
+        })
+    }
+
+    /// Saves the cache if it has changed since the last save
+    pub fn save(&mut self) {
+        if !self.dirty {
+            return;
+        }
+
+        match self.save_internal() {
+            Ok(()) => self.dirty = false,
+            Err(error) => log::error!("Could not save Steam cache {}: {}", self.filename, error),
+        }
+    }
+
+    fn save_internal(&self) -> BoxResult<()> {
+        let mut players: Vec<&CachedPlayer> = self.players.values().collect();
+        players.sort_by_key(|p| p.steamid.to_u64());
+
+        let json = serde_json::to_string(&serde_json::json!({ "players": players }))?;
+        let mut f = File::create(&self.filename)?;
+        f.write_all(json.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Forget everything cached for a player, so it's fetched again
+    pub fn invalidate(&mut self, steamid: SteamID) {
+        if self.players.remove(&steamid).is_some() {
+            self.dirty = true;
+        }
+    }
+
+    fn player_mut(&mut self, steamid: SteamID) -> &mut CachedPlayer {
+        self.dirty = true;
+        self.players
+            .entry(steamid)
+            .or_insert_with(|| CachedPlayer::new(steamid))
+    }
+
+    pub fn summary(&self, steamid: SteamID) -> Option<&SteamApiPlayer> {
+        let player = self.players.get(&steamid)?;
+        fresh(&player.summary, self.settings.summaries_hours)
+    }
+
+    pub fn set_summary(&mut self, steamid: SteamID, summary: SteamApiPlayer) {
+        self.player_mut(steamid).summary = Some(Cached::new(summary));
+    }
+
+    pub fn bans(&self, steamid: SteamID) -> Option<&SteamApiPlayerBans> {
+        let player = self.players.get(&steamid)?;
+        fresh(&player.bans, self.settings.bans_hours)
+    }
+
+    pub fn set_bans(&mut self, steamid: SteamID, bans: SteamApiPlayerBans) {
+        self.player_mut(steamid).bans = Some(Cached::new(bans));
+    }
+
+    pub fn owned_games(&self, steamid: SteamID) -> Option<&SteamApiOwnedGames> {
+        let player = self.players.get(&steamid)?;
+        fresh(&player.owned_games, self.settings.playtime_hours)
+    }
+
+    pub fn set_owned_games(&mut self, steamid: SteamID, owned_games: SteamApiOwnedGames) {
+        self.player_mut(steamid).owned_games = Some(Cached::new(owned_games));
+    }
+
+    /// Some(None) if the friend list is cached as private
+    pub fn friend_list(&self, steamid: SteamID) -> Option<&Option<Vec<SteamApiFriend>>> {
+        let player = self.players.get(&steamid)?;
+        fresh(&player.friend_list, self.settings.friend_lists_hours)
+    }
+
+    pub fn set_friend_list(&mut self, steamid: SteamID, friends: Option<Vec<SteamApiFriend>>) {
+        self.player_mut(steamid).friend_list = Some(Cached::new(friends));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bans(steamid: SteamID) -> SteamApiPlayerBans {
+        SteamApiPlayerBans {
+            steam_id: steamid.to_u64().to_string(),
+            community_banned: false,
+            number_of_vac_bans: 1,
+            days_since_last_ban: 10,
+            number_of_game_bans: 0,
+            economy_ban: "none".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_ttl() {
+        let settings = SteamCacheSettings {
+            summaries_hours: 24,
+            bans_hours: 0,
+            playtime_hours: 24,
+            friend_lists_hours: 24,
+        };
+        let mut cache = SteamCache {
+            settings,
+            ..Default::default()
+        };
+        let steamid = SteamID::from_u64(76561197960435530);
+
+        assert!(cache.bans(steamid).is_none());
+
+        // A zero TTL means bans are always fetched
+        cache.set_bans(steamid, bans(steamid));
+        assert!(cache.bans(steamid).is_none());
+
+        // Private friend lists are cached too
+        cache.set_friend_list(steamid, None);
+        assert!(matches!(cache.friend_list(steamid), Some(None)));
+
+        // Expired replies
+        cache.players.get_mut(&steamid).unwrap().friend_list = Some(Cached {
+            fetched: Local::now() - Duration::hours(25),
+            data: None,
+        });
+        assert!(cache.friend_list(steamid).is_none());
+
+        cache.invalidate(steamid);
+        assert!(cache.players.is_empty());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let filename = std::env::temp_dir().join("tf2monitor_test_steam_cache.json");
+        let filename = filename.to_str().unwrap();
+        let settings = SteamCacheSettings::default();
+
+        let fresh_id = SteamID::from_u64(76561197960435530);
+        let old_id = SteamID::from_u64(76561197994359533);
+
+        let mut cache = SteamCache {
+            filename: filename.to_string(),
+            settings: settings.clone(),
+            ..Default::default()
+        };
+        cache.set_bans(fresh_id, bans(fresh_id));
+        cache.set_friend_list(
+            fresh_id,
+            Some(vec![SteamApiFriend {
+                steamid: old_id.to_u64().to_string(),
+            }]),
+        );
+        cache.set_bans(old_id, bans(old_id));
+        cache
+            .players
+            .get_mut(&old_id)
+            .unwrap()
+            .bans
+            .as_mut()
+            .unwrap()
+            .fetched = Local::now() - Duration::hours(settings.max_hours() as i64 + 1);
+        cache.save();
+        assert!(!cache.dirty);
+
+        let loaded = SteamCache::load(filename, &settings).unwrap();
+        let _ = std::fs::remove_file(filename);
+
+        // Players with only expired replies are dropped
+        assert_eq!(loaded.players.len(), 1);
+        assert_eq!(loaded.bans(fresh_id).unwrap().number_of_vac_bans, 1);
+        assert_eq!(
+            loaded.friend_list(fresh_id).unwrap().as_ref().unwrap()[0].steamid,
+            old_id.to_u64().to_string()
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use super::{SteamApi, SteamReply, SteamRequest, SteamWebApi};
+use crate::{
+    appbus::AppBus,
+    models::{app_settings::AppSettings, steamid::SteamID},
+};
+use bus::BusReader;
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+};
+
+pub struct SteamThread {
+    bus: Arc<Mutex<AppBus>>,
+    steam_request_bus_rx: BusReader<SteamRequest>,
+    steam_api: Box<dyn SteamApi + Send>,
+}
+
+/// Start the background thread that fetches Steam info for the lobby thread.
+/// The Steam API can retry for a while, so this is kept off the lobby thread.
+pub fn start(settings: &AppSettings, bus: &Arc<Mutex<AppBus>>) -> thread::JoinHandle<()> {
+    let steam_api = Box::new(SteamWebApi::new(settings));
+    let mut steam_thread = SteamThread::new(bus, steam_api);
+
+    thread::spawn(move || steam_thread.run())
+}
+
+impl SteamThread {
+    /// The Steam API is passed in, so the lobby can be tested with a fake Steam API
+    pub fn new(bus: &Arc<Mutex<AppBus>>, steam_api: Box<dyn SteamApi + Send>) -> Self {
+        let steam_request_bus_rx = bus.lock().unwrap().steam_request_bus.add_rx();
+        Self {
+            bus: Arc::clone(bus),
+            steam_request_bus_rx,
+            steam_api,
+        }
+    }
+
+    pub fn run(&mut self) {
+        log::info!("Steam background thread started");
+
+        while let Ok(request) = self.steam_request_bus_rx.recv() {
+            self.fetch(request);
+        }
+    }
+
+    /// Fetch what the lobby thread asked for. Each kind of info is sent back as soon as
+    /// it's fetched, so the slow per-player calls don't hold back the rest.
+    pub fn fetch(&mut self, request: SteamRequest) {
+        for steamid in request.refresh {
+            self.steam_api.force_refresh(steamid);
+        }
+
+        if !self.steam_api.has_key() {
+            return;
+        }
+
+        // The players found now need their bans, playtime and friends too
+        let mut found = Vec::new();
+        if !request.summaries.is_empty() {
+            match self.steam_api.get_player_summaries(request.summaries) {
+                Ok(players) => {
+                    found = players
+                        .iter()
+                        .filter_map(|p| SteamID::from_u64_string(&p.steamid))
+                        .collect();
+                    self.reply(SteamReply::Summaries(players));
+                }
+                Err(e) => log::warn!("Could not fetch Steam info: {}", e),
+            }
+        }
+
+        let steamids = [request.bans, found.clone()].concat();
+        if !steamids.is_empty() {
+            match self.steam_api.get_player_bans(steamids) {
+                Ok(bans) => self.reply(SteamReply::Bans(bans)),
+                Err(e) => log::warn!("Could not fetch Steam bans: {}", e),
+            }
+        }
+
+        // One call per player. On errors, the rest are tried in the next request.
+        let mut owned_games = Vec::new();
+        for steamid in [request.playtime, found.clone()].concat() {
+            match self.steam_api.get_owned_games(steamid) {
+                Ok(games) => owned_games.push((steamid, games)),
+                Err(e) => {
+                    log::warn!("Could not fetch TF2 playtime: {}", e);
+                    break;
+                }
+            }
+        }
+        if !owned_games.is_empty() {
+            self.reply(SteamReply::OwnedGames(owned_games));
+        }
+
+        let mut friend_lists = Vec::new();
+        for steamid in [request.friends, found].concat() {
+            match self.steam_api.get_friend_list(steamid) {
+                Ok(friends) => friend_lists.push((steamid, friends)),
+                Err(e) => {
+                    log::warn!("Could not fetch friend list: {}", e);
+                    break;
+                }
+            }
+        }
+        if !friend_lists.is_empty() {
+            self.reply(SteamReply::FriendLists(friend_lists));
+        }
+
+        self.steam_api.save_cache();
+    }
+
+    fn reply(&self, reply: SteamReply) {
+        AppBus::send_steam_reply(&self.bus, reply);
+    }
+}